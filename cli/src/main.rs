@@ -7,6 +7,10 @@ use argh::FromArgs;
 use sub_commands::password::PasswordSubCommand;
 
 use crate::sub_commands::backup::BackupSubCommand;
+use crate::sub_commands::highlight_css::HighlightCssSubCommand;
+use crate::sub_commands::rerender::RerenderSubCommand;
+use crate::sub_commands::session_key::RotateSessionKeySubCommand;
+use crate::sub_commands::sessions::SessionsSubCommand;
 
 mod sub_commands;
 
@@ -16,6 +20,10 @@ mod sub_commands;
 enum SubCommandEnum {
     Password(PasswordSubCommand),
     Backup(BackupSubCommand),
+    Sessions(SessionsSubCommand),
+    RotateSessionKey(RotateSessionKeySubCommand),
+    Rerender(RerenderSubCommand),
+    HighlightCss(HighlightCssSubCommand),
 }
 
 #[derive(FromArgs, Debug)]
@@ -49,6 +57,10 @@ fn main() {
         match sub_cmd {
             SubCommandEnum::Password(cmd) => cmd.run(&args),
             SubCommandEnum::Backup(cmd) => cmd.run(&args),
+            SubCommandEnum::Sessions(cmd) => cmd.run(&args),
+            SubCommandEnum::RotateSessionKey(cmd) => cmd.run(&args),
+            SubCommandEnum::Rerender(cmd) => cmd.run(&args),
+            SubCommandEnum::HighlightCss(cmd) => cmd.run(&args),
         }
     } else {
         core::run(args.conf, args.no_password);
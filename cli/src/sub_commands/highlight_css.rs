@@ -0,0 +1,59 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use argh::FromArgs;
+
+use crate::Run;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// print the css of a code highlight theme
+#[argh(subcommand, name = "highlight-css")]
+pub struct HighlightCssSubCommand {
+    #[argh(option, short = 't')]
+    /// theme name, defaults to `render.highlight.theme`
+    theme: Option<String>,
+
+    #[argh(option, short = 'o')]
+    /// write to a file instead of stdout
+    output: Option<PathBuf>,
+
+    #[argh(switch)]
+    /// list available themes
+    list: bool,
+}
+
+impl HighlightCssSubCommand {
+    pub fn run(&self, args: &Run) {
+        if self.list {
+            utils::markdown::highlight::themes()
+                .for_each(|theme| println!("{}", theme));
+            return;
+        }
+
+        config::init(args.conf.iter().map(|s| s.into()).collect())
+            .expect("config error");
+        let theme = self.theme.clone().unwrap_or_else(|| {
+            config::get_config_temp()
+                .render()
+                .highlight()
+                .theme()
+                .to_string()
+        });
+        let css = utils::markdown::highlight::css(&theme).unwrap();
+
+        match &self.output {
+            Some(path) => {
+                let mut file = OpenOptions::new()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path)
+                    .unwrap();
+                file.write_all(css.as_bytes()).unwrap();
+                println!("css of `{}` written to {}", theme, path.display());
+            }
+            None => print!("{}", css),
+        }
+    }
+}
@@ -1,2 +1,6 @@
 pub mod backup;
+pub mod highlight_css;
 pub mod password;
+pub mod rerender;
+pub mod session_key;
+pub mod sessions;
@@ -0,0 +1,29 @@
+use argh::FromArgs;
+
+use crate::Run;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// re-render the html of all posts after the markdown options changed
+#[argh(subcommand, name = "rerender")]
+pub struct RerenderSubCommand {}
+
+impl RerenderSubCommand {
+    pub fn run(&self, args: &Run) {
+        config::init(args.conf.iter().map(|s| s.into()).collect())
+            .expect("config error");
+        // 主题中的shortcode也要参与渲染
+        template::load_shortcodes().expect("shortcode templates error");
+
+        let count = Result::<_, anyhow::Error>::unwrap(
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let db = database::new().await?;
+                    database::models::post::Post::rerender_all(&db).await
+                }),
+        );
+        println!("{} posts re-rendered", count);
+    }
+}
@@ -0,0 +1,29 @@
+use argh::FromArgs;
+
+use crate::Run;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// rotate the key used by the cookie session store
+#[argh(subcommand, name = "rotate-session-key")]
+pub struct RotateSessionKeySubCommand {
+    #[argh(switch)]
+    /// drop the old key immediately instead of keeping it for the grace period
+    no_grace: bool,
+}
+
+impl RotateSessionKeySubCommand {
+    pub fn run(&self, args: &Run) {
+        config::init(args.conf.iter().map(|s| s.into()).collect())
+            .expect("config error");
+        let config = config::get_config_temp();
+        http::rotate_key(config.data_path(), !self.no_grace).unwrap();
+        if self.no_grace {
+            println!("session key rotated, all sessions are invalidated");
+        } else {
+            println!(
+                "session key rotated, the old key is accepted for {}",
+                config.http().cookie_key_grace()
+            );
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+
+use argh::FromArgs;
+use async_session::SessionStore as _;
+
+use http::SessionInfo;
+
+use crate::Run;
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// manage login sessions
+#[argh(subcommand, name = "sessions")]
+pub struct SessionsSubCommand {
+    #[argh(subcommand)]
+    action: SessionsAction,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+#[argh(subcommand)]
+enum SessionsAction {
+    List(ListAction),
+    Revoke(RevokeAction),
+    RevokeAll(RevokeAllAction),
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// list active sessions
+#[argh(subcommand, name = "list")]
+struct ListAction {}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// revoke a session
+#[argh(subcommand, name = "revoke")]
+struct RevokeAction {
+    #[argh(positional)]
+    /// session id
+    id: String,
+}
+
+#[derive(FromArgs, PartialEq, Debug)]
+/// revoke all sessions
+#[argh(subcommand, name = "revoke-all")]
+struct RevokeAllAction {}
+
+impl SessionsSubCommand {
+    pub fn run(&self, args: &Run) {
+        config::init(args.conf.iter().map(|s| s.into()).collect())
+            .expect("config error");
+
+        Result::<_, anyhow::Error>::unwrap(
+            tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async {
+                    let db = Arc::new(database::new().await?);
+                    let store = http::open_session_store(&db).await?;
+                    match &self.action {
+                        SessionsAction::List(_) => {
+                            for session in store.list_sessions().await? {
+                                print_session(&SessionInfo::from(
+                                    &session,
+                                ));
+                            }
+                        }
+                        SessionsAction::Revoke(action) => {
+                            match store.find_session(&action.id).await? {
+                                Some(session) => {
+                                    store.destroy_session(session).await?;
+                                    println!("session revoked");
+                                }
+                                None => println!("session not found"),
+                            }
+                        }
+                        SessionsAction::RevokeAll(_) => {
+                            store.clear_store().await?;
+                            println!("all sessions revoked");
+                        }
+                    }
+                    Ok(())
+                }),
+        );
+    }
+}
+
+fn print_session(info: &SessionInfo) {
+    println!("{}", info.id);
+    if let Some(expiry) = &info.expiry {
+        println!("    expiry:     {}", expiry.to_rfc3339());
+    }
+    if let Some(meta) = &info.meta {
+        println!("    created:    {}", meta.create_time.to_rfc3339());
+        println!("    last seen:  {}", meta.last_seen.to_rfc3339());
+        println!(
+            "    ip:         {}",
+            meta.ip.as_deref().unwrap_or("-")
+        );
+        println!(
+            "    user agent: {}",
+            meta.user_agent.as_deref().unwrap_or("-")
+        );
+    }
+}
@@ -95,9 +95,11 @@ impl Config {
                 .separator("__"),
         )?;
 
+        check_removed_keys(&c)?;
         let maop_config = c.clone().try_into::<MaopConfig>()?;
 
         Config::create_data_dir(maop_config.data_path())?;
+        utils::markdown::set_options(maop_config.render().markdown_options());
 
         let config = Config {
             inner: Arc::new(ArcSwap::from_pointee(maop_config)),
@@ -112,12 +114,19 @@ impl Config {
     }
 
     fn refresh(&self) -> anyhow::Result<()> {
+        utils::sd_notify::reloading().ok();
+        utils::defer!(|| {
+            utils::sd_notify::ready().ok();
+        });
+
         let mut config = self.raw.lock();
         config.refresh()?;
 
+        check_removed_keys(&config)?;
         let maop_config = config.clone().try_into::<MaopConfig>()?;
 
         Config::create_data_dir(maop_config.data_path())?;
+        utils::markdown::set_options(maop_config.render().markdown_options());
 
         self.inner.store(Arc::new(maop_config));
 
@@ -174,6 +183,22 @@ impl Config {
     }
 }
 
+/// 换成了`[[http.listen]]`的旧配置, 忽略它们会悄悄地监听在默认地址上
+const REMOVED_KEYS: [&str; 3] = ["http.bind", "http.port", "http.type"];
+
+fn check_removed_keys(c: &config_rs::Config) -> anyhow::Result<()> {
+    for key in REMOVED_KEYS {
+        if c.get::<config_rs::Value>(key).is_ok() {
+            anyhow::bail!(
+                "`{}` has been removed, move `http.type`, `http.bind` and `http.port` \
+                 into a `[[http.listen]]` entry with `type`, `bind` and `port`",
+                key
+            );
+        }
+    }
+    Ok(())
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config::new(Vec::new()).unwrap()
@@ -11,5 +11,8 @@ crate::gen_config!(DatabaseConfig, {
     shared_cache: bool,
     statement_cache_capacity: usize,
 
-    page_size: u32
+    page_size: u32,
+
+    purge_deleted_comments_after: TimeUnit,
+    purge_check_interval: TimeUnit
 });
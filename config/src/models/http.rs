@@ -1,18 +1,102 @@
+use std::net::IpAddr;
+use std::path::PathBuf;
+
 use compact_str::CompactString;
+use utils::unit::byte_unit::ByteUnit;
 use utils::unit::time_unit::TimeUnit;
 
 #[derive(serde::Deserialize, serde::Serialize, Debug, Copy, Clone)]
 #[serde(rename_all = "lowercase")]
 pub enum ListenType {
     Http,
+    Https,
     Uds,
 }
 
-crate::gen_config!(HttpConfig, {
-    bind: CompactString,
-    port: u16,
+#[rustfmt::skip]
+crate::gen_config!(AcmeConfig, {
+    #[serde(default = "default_acme_directory")]
+    directory: CompactString,
+    domains: Vec<CompactString>,
+    #[serde(default)]
+    contact: Vec<CompactString>,
+    #[serde(default = "default_acme_renew_before")]
+    renew_before: TimeUnit
+});
+
+#[inline]
+fn default_acme_directory() -> CompactString {
+    CompactString::from("https://acme-v02.api.letsencrypt.org/directory")
+}
+
+#[inline]
+fn default_acme_renew_before() -> TimeUnit {
+    "30d".parse().unwrap()
+}
+
+crate::gen_config!(TlsConfig, {
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    acme: Option<AcmeConfig>,
+    redirect_port: Option<u16>,
+    hsts: Option<TimeUnit>
+});
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreType {
+    File,
+    Sqlite,
+    Cookie,
+    Rocksdb,
+    Redis,
+}
+
+crate::gen_config!(RedisConfig, {
+    addr: CompactString,
+    db: u32,
+    password: Option<CompactString>,
+    key_prefix: CompactString
+});
+
+crate::gen_config!(CompressionConfig, {
+    enable: bool,
+    min_size: ByteUnit,
+    mime_types: Vec<CompactString>
+});
+
+crate::gen_config!(CacheControlConfig, {
+    pages: CompactString,
+    api: CompactString,
+    assets: CompactString
+});
+
+#[rustfmt::skip]
+crate::gen_config!(MetricsConfig, {
+    enable: bool,
+    token: Option<CompactString>,
+    #[serde(default)]
+    allow: Vec<IpAddr>,
+    #[serde(default)]
+    allow_unix: bool
+});
+
+crate::gen_config!(ListenConfig, {
     r#type: ListenType,
+    bind: CompactString,
+    port: Option<u16>
+});
+
+crate::gen_config!(HttpConfig, {
+    listen: Vec<ListenConfig>,
+    tls: Option<TlsConfig>,
     session_expiry: TimeUnit,
+    session_store: SessionStoreType,
+    redis: RedisConfig,
+    cookie_key_grace: TimeUnit,
     overdue_check_interval: TimeUnit,
-    cors: Vec<CompactString>
+    cors: Vec<CompactString>,
+    compression: CompressionConfig,
+    cache_control: CacheControlConfig,
+    metrics: MetricsConfig
 });
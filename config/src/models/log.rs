@@ -2,7 +2,20 @@ use std::collections::HashMap;
 use compact_str::CompactString;
 use _log::Level;
 
+#[derive(serde::Deserialize, serde::Serialize, Debug, Copy, Clone)]
+#[serde(rename_all = "lowercase")]
+pub enum AccessLogFormat {
+    Combined,
+    Json,
+}
+
+crate::gen_config!(AccessLogConfig, {
+    enable: bool,
+    format: AccessLogFormat
+});
+
 crate::gen_config!(LogConfig, {
     filter: HashMap<CompactString, Level>,
-    level: Level
+    level: Level,
+    access: AccessLogConfig
 });
@@ -1,3 +1,69 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
 
-crate::gen_config!(RenderConfig, { strict_mode: bool, dev_mode: bool, template: Option<PathBuf> });
+use compact_str::CompactString;
+use utils::markdown::sanitize::SanitizePolicy;
+use utils::markdown::MarkdownOptions;
+use utils::unit::byte_unit::ByteUnit;
+
+crate::gen_config!(HighlightConfig, { enable: bool, theme: CompactString, line_numbers: bool });
+
+#[rustfmt::skip]
+crate::gen_config!(SanitizePolicyConfig, {
+    #[serde(default)]
+    tags: Vec<CompactString>,
+    #[serde(default)]
+    attributes: HashMap<CompactString, Vec<CompactString>>,
+    #[serde(default)]
+    url_schemes: Vec<CompactString>,
+    link_rel: Option<CompactString>,
+    external_target: Option<CompactString>
+});
+
+crate::gen_config!(SanitizeConfig, { post_enable: bool, post: SanitizePolicyConfig, comment: SanitizePolicyConfig });
+
+#[derive(serde::Deserialize, serde::Serialize, Debug, Copy, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CommentOrder {
+    Oldest,
+    Newest,
+}
+
+crate::gen_config!(CommentsConfig, { max_depth: u32, order: CommentOrder, reply_order: CommentOrder });
+
+crate::gen_config!(RenderConfig, { strict_mode: bool, dev_mode: bool, template: Option<PathBuf>, page_cache: ByteUnit, math: bool, heading_anchors: bool, excerpt_length: usize, highlight: HighlightConfig, sanitize: SanitizeConfig, comments: CommentsConfig });
+
+impl SanitizePolicyConfig {
+    fn policy(&self, keep_comments: bool) -> SanitizePolicy {
+        let strings = |list: &[CompactString]| list.iter().map(|s| s.to_string()).collect();
+        SanitizePolicy {
+            tags: strings(&self.tags),
+            attributes: self
+                .attributes
+                .iter()
+                .map(|(tag, attributes)| (tag.to_string(), strings(attributes)))
+                .collect(),
+            url_schemes: strings(&self.url_schemes),
+            link_rel: self.link_rel.as_ref().map(|s| s.to_string()),
+            external_target: self.external_target.as_ref().map(|s| s.to_string()),
+            keep_comments,
+        }
+    }
+}
+
+impl RenderConfig {
+    pub fn markdown_options(&self) -> MarkdownOptions {
+        MarkdownOptions {
+            highlight: self.highlight.enable,
+            line_numbers: self.highlight.line_numbers,
+            math: self.math,
+            heading_anchors: self.heading_anchors,
+            // 文章中`[[post:id]]`的标记是html注释
+            post_policy: self
+                .sanitize
+                .post_enable
+                .then(|| self.sanitize.post.policy(true)),
+            comment_policy: self.sanitize.comment.policy(false),
+        }
+    }
+}
@@ -7,6 +7,7 @@ use futures::TryFutureExt;
 use tokio::runtime::Builder;
 
 use global_resource::SHUTDOWN_NOTIFY;
+use timer::{Follow, Task};
 
 #[cfg(feature = "prof")]
 mod prof;
@@ -66,6 +67,7 @@ pub fn run(configs: Vec<String>, no_password: bool) {
             }
 
         utils::task::spawn(global_resource::TIME_WHEEL.run(), "timer");
+        start_watchdog();
 
         let join_handle = utils::task::spawn(async move {
             if no_password {
@@ -80,6 +82,10 @@ pub fn run(configs: Vec<String>, no_password: bool) {
         ).unwrap();
 
         log::info!("Shutting down...");
+        // 热升级后systemd的主进程已经是新进程了, 不能让它以为服务在停止
+        if !http::handed_over() {
+            utils::sd_notify::stopping().ok();
+        }
 
         if tokio::time::timeout(
             shutdown_timeout,
@@ -98,3 +104,21 @@ pub fn run(configs: Vec<String>, no_password: bool) {
     #[cfg(feature = "prof")]
     prof::report(&guard);
 }
+
+/// 由时间轮发送watchdog通知, 时间轮卡住时systemd会重启服务
+fn start_watchdog() {
+    if let Some(timeout) = utils::sd_notify::watchdog_timeout() {
+        log::info!("systemd watchdog enabled, timeout: {:?}", timeout);
+        global_resource::TIME_WHEEL.add_task(Task::interval(
+            || {
+                Box::pin(async {
+                    if let Err(err) = utils::sd_notify::watchdog() {
+                        log::error!("failed to notify watchdog: {}", err);
+                    }
+                    Follow::Done
+                })
+            },
+            timeout / 2,
+        ));
+    }
+}
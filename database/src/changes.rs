@@ -0,0 +1,27 @@
+use std::sync::RwLock;
+
+use once_cell::sync::Lazy;
+
+/// 写操作成功后通知的变化, 用来让依赖数据的缓存失效
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Change {
+    /// 文章本身被创建, 修改或删除
+    Post(u32),
+    /// 文章下的评论发生了变化
+    Comment { post_id: u32 },
+    /// 整表恢复之类无法精确描述的变化
+    All,
+}
+
+type Hook = Box<dyn Fn(Change) + Send + Sync>;
+
+static HOOKS: Lazy<RwLock<Vec<Hook>>> = Lazy::new(Default::default);
+
+/// 注册的函数会在写操作所在的任务中同步调用, 不要在里面阻塞
+pub fn hook(f: Hook) {
+    HOOKS.write().unwrap().push(f);
+}
+
+pub(crate) fn notify(change: Change) {
+    HOOKS.read().unwrap().iter().for_each(|f| f(change));
+}
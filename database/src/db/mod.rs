@@ -1,13 +1,36 @@
 use anyhow::Context;
-use sea_orm::{ConnectionTrait, Schema, SqlxSqliteConnector};
+use once_cell::sync::OnceCell;
+use sea_orm::sea_query::Expr;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Schema,
+    SqlxSqliteConnector, Statement, TransactionTrait,
+};
 use sea_orm::{DatabaseConnection, DbBackend};
 use sqlx_core::connection::ConnectOptions;
 use sqlx_core::pool::PoolOptions;
 use sqlx_core::sqlite::{
-    SqliteConnectOptions, SqliteJournalMode, SqliteLockingMode,
+    SqliteConnectOptions, SqliteJournalMode, SqliteLockingMode, SqlitePool,
     SqliteSynchronous,
 };
 
+/// sea-orm不暴露连接池, 保留一份用于统计
+static POOL: OnceCell<SqlitePool> = OnceCell::new();
+
+#[derive(Debug, Clone, Copy)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: usize,
+    pub max: u32,
+}
+
+pub fn pool_stats() -> Option<PoolStats> {
+    POOL.get().map(|pool| PoolStats {
+        size: pool.size(),
+        idle: pool.num_idle(),
+        max: *config::get_config_temp().database().max_conn(),
+    })
+}
+
 pub async fn new() -> anyhow::Result<DatabaseConnection> {
     let config_full = config::get_config_full();
     let config = config_full.database();
@@ -28,24 +51,32 @@ pub async fn new() -> anyhow::Result<DatabaseConnection> {
         *config.warn_time().duration(),
     );
 
-    let db = SqlxSqliteConnector::from_sqlx_sqlite_pool(
-        PoolOptions::new()
-            .max_connections(*config.max_conn())
-            .min_connections(*config.min_conn())
-            .connect_timeout(*config.timeout().duration())
-            .max_lifetime(*config.max_lifetime().duration())
-            .idle_timeout(*config.idle_timeout().duration())
-            .test_before_acquire(true)
-            .connect_with(opt)
-            .await
-            .context("connect to database")?,
-    );
+    let pool = PoolOptions::new()
+        .max_connections(*config.max_conn())
+        .min_connections(*config.min_conn())
+        .connect_timeout(*config.timeout().duration())
+        .max_lifetime(*config.max_lifetime().duration())
+        .idle_timeout(*config.idle_timeout().duration())
+        .test_before_acquire(true)
+        .connect_with(opt)
+        .await
+        .context("connect to database")?;
+    POOL.set(pool.clone()).ok();
+    let db = SqlxSqliteConnector::from_sqlx_sqlite_pool(pool);
 
     setup_schema(&db).await.context("setup schema")?;
 
     Ok(db)
 }
 
+const POST_RENDERED_COLUMNS: [(&str, &str); 5] = [
+    ("content_html", "TEXT NOT NULL DEFAULT ''"),
+    ("excerpt_html", "TEXT NOT NULL DEFAULT ''"),
+    ("word_count", "INTEGER NOT NULL DEFAULT 0"),
+    ("reading_minutes", "INTEGER NOT NULL DEFAULT 0"),
+    ("toc_json", "TEXT NOT NULL DEFAULT ''"),
+];
+
 async fn setup_schema(db: &DatabaseConnection) -> anyhow::Result<()> {
     db.execute(
         db.get_database_backend().build(
@@ -57,6 +88,27 @@ async fn setup_schema(db: &DatabaseConnection) -> anyhow::Result<()> {
     .await
     .context("create posts")?;
 
+    // 后来加上的列, 旧的数据库需要补上并重新渲染.
+    // 放在同一个事务中, 渲染失败时下次启动会重新加列和渲染
+    let txn = db.begin().await.context("begin posts migration")?;
+    let mut added = false;
+    for (column, definition) in POST_RENDERED_COLUMNS {
+        if !column_exists(&txn, "posts", column).await? {
+            txn.execute(Statement::from_string(
+                DbBackend::Sqlite,
+                format!(r#"ALTER TABLE "posts" ADD COLUMN "{}" {}"#, column, definition),
+            ))
+            .await
+            .with_context(|| format!("add posts.{}", column))?;
+            added = true;
+        }
+    }
+    if added {
+        let count = crate::models::post::Post::rerender(&txn).await?;
+        log::info!("re-rendered {} posts", count);
+    }
+    txn.commit().await.context("commit posts migration")?;
+
     db.execute(
         db.get_database_backend().build(
             Schema::new(DbBackend::Sqlite)
@@ -68,5 +120,67 @@ async fn setup_schema(db: &DatabaseConnection) -> anyhow::Result<()> {
     )
     .await
     .context("create comments")?;
+
+    let txn = db.begin().await.context("begin comments migration")?;
+    if !column_exists(&txn, "comments", "deleted_time").await? {
+        txn.execute(Statement::from_string(
+            DbBackend::Sqlite,
+            r#"ALTER TABLE "comments" ADD COLUMN "deleted_time" TEXT NULL"#.to_owned(),
+        ))
+        .await
+        .context("add comments.deleted_time")?;
+        // 之前删除的评论从现在开始计时
+        crate::models::comment::Comment::update_many()
+            .col_expr(
+                crate::models::comment::Column::DeletedTime,
+                Expr::value(chrono::Local::now().naive_local()),
+            )
+            .filter(crate::models::comment::Column::Deleted.eq(true))
+            .exec(&txn)
+            .await
+            .context("set comments.deleted_time")?;
+    }
+    txn.commit().await.context("commit comments migration")?;
+
+    db.execute(
+        db.get_database_backend().build(
+            Schema::new(DbBackend::Sqlite)
+                .create_table_from_entity(
+                    crate::models::session::Entity,
+                )
+                .if_not_exists(),
+        ),
+    )
+    .await
+    .context("create sessions")?;
+
+    db.execute(Statement::from_string(
+        DbBackend::Sqlite,
+        r#"CREATE INDEX IF NOT EXISTS "idx_sessions_expiry" ON "sessions" ("expiry")"#
+            .to_owned(),
+    ))
+    .await
+    .context("create idx_sessions_expiry")?;
     Ok(())
 }
+
+/// 给已有的表加列之前检查, sqlite的`ADD COLUMN`不支持`IF NOT EXISTS`
+async fn column_exists(
+    db: &impl ConnectionTrait,
+    table: &str,
+    column: &str,
+) -> anyhow::Result<bool> {
+    let rows = db
+        .query_all(Statement::from_string(
+            DbBackend::Sqlite,
+            format!(r#"PRAGMA table_info("{}")"#, table),
+        ))
+        .await
+        .with_context(|| format!("table_info {}", table))?;
+    for row in rows {
+        if row.try_get::<String>("", "name")? == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
@@ -2,8 +2,10 @@
 #![feature(type_ascription)]
 #![feature(decl_macro)]
 
-pub use crate::db::new;
+pub use crate::changes::{hook, Change};
+pub use crate::db::{new, pool_stats, PoolStats};
 
+mod changes;
 mod db;
 pub mod models;
 
@@ -114,6 +116,9 @@ mod test {
 
         assert_eq!(post.title, "new title");
         assert_eq!(post.content, "new content");
+        assert_eq!(post.content_html, "<p>new content</p>\n");
+        assert_eq!(post.word_count, 2);
+        assert_eq!(post.reading_minutes, 1);
 
         let id = post.id;
         post.delete(&db).await.unwrap();
@@ -9,6 +9,8 @@ use sea_orm::{
     RelationTrait,
 };
 
+use crate::changes::{notify, Change};
+
 use super::def_fn;
 
 pub type Comment = Entity;
@@ -35,6 +37,10 @@ pub struct Model {
 
     /// 已经删除(对用户而言)
     pub deleted: bool,
+    /// 软删除的时间, 超过`database.purge_deleted_comments_after`后彻底删除
+    #[sea_orm(nullable)]
+    #[serde(default)]
+    pub deleted_time: Option<NaiveDateTime>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter)]
@@ -106,33 +112,80 @@ impl Comment {
                 let active_model = Into::<ActiveModel>::into(comment);
                 active_model.insert(db).await.context("Comment::recover::insert")?;
             }
+            notify(Change::All);
             Ok(())
         }
     );
 
     def_fn!(
         hard_delete(db, id: u32) -> () {
+            let post_id = Comment::find_one(db, id).await?.map(|comment| comment.post_id);
             (DeleteComment {
                 id
             }).into_active_model()
                 .delete(db)
                 .await
-                .map(|_| {})
+                .map(|_| post_id.into_iter().for_each(|post_id| notify(Change::Comment { post_id })))
                 .context("Comment::hard_delete")
         }
     );
 
     def_fn!(
-        soft_delete(db, id: u32) -> () {
-            (ActiveModel {
-                id: ActiveValue::set(id),
-                deleted: ActiveValue::set(true),
-                ..Default::default()
-            }).into_active_model()
-                .delete(db)
+        soft_delete(db, id: u32) -> bool {
+            let now = chrono::Local::now().naive_local();
+            Comment::set_deleted(db, id, Some(now)).await.context("Comment::soft_delete")
+        }
+    );
+
+    def_fn!(
+        restore(db, id: u32) -> bool {
+            Comment::set_deleted(db, id, None).await.context("Comment::restore")
+        }
+    );
+
+    /// 评论不存在时返回`false`
+    async fn set_deleted(
+        db: &DatabaseConnection,
+        id: u32,
+        deleted_time: Option<NaiveDateTime>,
+    ) -> anyhow::Result<bool> {
+        let comment = match Comment::find_by_id(id).one(db).await? {
+            Some(comment) => comment,
+            None => return Ok(false),
+        };
+        let mut active_model = Into::<ActiveModel>::into(comment);
+        active_model.deleted = ActiveValue::set(deleted_time.is_some());
+        active_model.deleted_time = ActiveValue::set(deleted_time);
+        let comment = active_model.update(db).await?;
+        notify(Change::Comment {
+            post_id: comment.post_id,
+        });
+        Ok(true)
+    }
+
+    def_fn!(
+        find_deleted(db) -> Vec<CommentModel> {
+            Comment::find()
+                .filter(Column::Deleted.eq(true))
+                .order_by_desc(Column::DeletedTime)
+                .all(db)
+                .await
+                .context("Comment::find_deleted")
+        }
+    );
+
+    def_fn!(
+        purge_deleted(db, before: NaiveDateTime) -> u64 {
+            let result = Comment::delete_many()
+                .filter(Column::Deleted.eq(true))
+                .filter(Column::DeletedTime.lt(before))
+                .exec(db)
                 .await
-                .map(|_| {})
-                .context("Comment::soft_delete")
+                .context("Comment::purge_deleted")?;
+            if result.rows_affected > 0 {
+                notify(Change::All);
+            }
+            Ok(result.rows_affected)
         }
     );
 
@@ -145,7 +198,7 @@ impl Comment {
     def_fn!(
         find_replies(db, id: u32) -> Vec<CommentModel> {
             Comment::find()
-            .filter(Column::ParentId.contains(&id.to_string()))
+            .filter(Column::ParentId.eq(id))
             .order_by_desc(Column::CreateTime)
             .all(db)
             .await
@@ -165,7 +218,10 @@ impl Comment {
             active_model.into_active_model()
                 .insert(db)
                 .await
-                .map(|am: Model| am.id)
+                .map(|am: Model| {
+                    notify(Change::Comment { post_id });
+                    am.id
+                })
                 .context("Comment::insert")
         }
     );
@@ -1,4 +1,4 @@
-utils::pub_mods!(post, comment);
+utils::pub_mods!(post, comment, session);
 
 macro def_fn {
     ($name:ident ($db:tt $(,)? $($param:ident: $ty:ty),*) -> $r:ty $body:block) => {
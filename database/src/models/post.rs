@@ -3,12 +3,16 @@ use chrono::NaiveDateTime;
 use sea_orm::DatabaseConnection;
 use sea_orm::{
     ActiveModelBehavior, ActiveModelTrait, ActiveValue, ColumnTrait,
-    DeriveEntityModel, DeriveIntoActiveModel, DerivePrimaryKey,
+    ConnectionTrait, DeriveEntityModel, DeriveIntoActiveModel, DerivePrimaryKey,
     DeriveRelation, EntityTrait, EnumIter, IdenStatic,
     IntoActiveModel, PrimaryKeyTrait, QueryFilter, Related,
     RelationDef, RelationTrait,
 };
 
+use utils::markdown::summary::Summary;
+use utils::markdown::toc::TocEntry;
+
+use crate::changes::{notify, Change};
 use crate::models::comment::{
     AsCommentId, Comment, CommentModel, NewComment,
 };
@@ -32,6 +36,18 @@ pub struct Model {
     pub id: u32,
     pub title: String,
     pub content: String,
+    /// `content`渲染后的html, markdown选项变化后需要`maop rerender`
+    #[serde(default)]
+    pub content_html: String,
+    #[serde(default)]
+    pub excerpt_html: String,
+    #[serde(default)]
+    pub word_count: u32,
+    #[serde(default)]
+    pub reading_minutes: u32,
+    /// 渲染时生成的目录, json格式, 通过`toc()`读取
+    #[serde(default, skip_serializing)]
+    pub toc_json: String,
 
     pub create_time: NaiveDateTime,
     pub last_modified_time: NaiveDateTime,
@@ -62,6 +78,35 @@ pub struct NewPost {
     pub content: String,
 }
 
+/// 由`content`生成的字段, 写入时一起更新
+struct Rendered {
+    content_html: String,
+    toc: Vec<TocEntry>,
+    summary: Summary,
+}
+
+impl Rendered {
+    fn new(content: &str) -> anyhow::Result<Self> {
+        let excerpt_length = *config::get_config_temp().render().excerpt_length();
+        let (content_html, toc) = utils::markdown::render_with_toc(content)?;
+        Ok(Rendered {
+            content_html,
+            toc,
+            summary: utils::markdown::summary::summary(content, excerpt_length)?,
+        })
+    }
+
+    fn set(self, active_model: &mut ActiveModel) {
+        active_model.content_html = ActiveValue::set(self.content_html);
+        active_model.toc_json = ActiveValue::set(
+            serde_json::to_string(&self.toc).unwrap_or_default(),
+        );
+        active_model.excerpt_html = ActiveValue::set(self.summary.excerpt_html);
+        active_model.word_count = ActiveValue::set(self.summary.word_count);
+        active_model.reading_minutes = ActiveValue::set(self.summary.reading_minutes);
+    }
+}
+
 impl Post {
     def_fn!(
         find_all(db) -> Vec<PostModel> {
@@ -76,9 +121,13 @@ impl Post {
                 .await
                 .context("Post::recover::delete_all")?;
             for post in posts {
-                let active_model = Into::<ActiveModel>::into(post);
+                // 旧的备份里没有渲染后的内容
+                let rendered = Rendered::new(&post.content)?;
+                let mut active_model = Into::<ActiveModel>::into(post);
+                rendered.set(&mut active_model);
                 active_model.insert(db).await.context("Post::recover::insert")?;
             }
+            notify(Change::All);
             Ok(())
         }
     );
@@ -96,7 +145,7 @@ impl Post {
             }).into_active_model()
                 .delete(db)
                 .await
-                .map(|_| {})
+                .map(|_| notify(Change::Post(id)))
                 .context("Post::delete")
         }
     );
@@ -107,6 +156,16 @@ impl Post {
         }
     );
 
+    def_fn!(
+        find_by_ids(db, ids: Vec<u32>) -> Vec<PostModel> {
+            Post::find()
+                .filter(Column::Id.is_in(ids))
+                .all(db)
+                .await
+                .context("Post::find_by_ids")
+        }
+    );
+
     def_fn!(
         find_and_commit(db, id: u32) -> Option<(PostModel, Vec<CommentModel>)> {
             Post::find_by_id(id)
@@ -121,13 +180,18 @@ impl Post {
     def_fn!(
         insert(db, new_post: NewPost) -> u32 {
             let now = chrono::Local::now().naive_local();
+            let rendered = Rendered::new(&new_post.content)?;
             let mut active_model = new_post.into_active_model();
+            rendered.set(&mut active_model);
             active_model.create_time = ActiveValue::set(now);
             active_model.last_modified_time = ActiveValue::set(now);
             active_model
                 .insert(db)
                 .await
-                .map(|am: Model| am.id)
+                .map(|am: Model| {
+                    notify(Change::Post(am.id));
+                    am.id
+                })
                 .context("Post::insert")
         }
     );
@@ -135,20 +199,54 @@ impl Post {
     def_fn!(
         update(db, id: u32, title: Option<String>, content: Option<String>) -> () {
             let now = chrono::Local::now().naive_local();
-            (ActiveModel {
+            let rendered = content.as_deref().map(Rendered::new).transpose()?;
+            let mut active_model = ActiveModel {
                 id: ActiveValue::set(id),
                 title: title.map(ActiveValue::set).unwrap_or_else(ActiveValue::not_set),
                 content: content.map(ActiveValue::set).unwrap_or_else(ActiveValue::not_set),
                 last_modified_time: ActiveValue::set(now),
                 ..Default::default()
-            }).into_active_model()
+            };
+            if let Some(rendered) = rendered {
+                rendered.set(&mut active_model);
+            }
+            active_model
                 .update(db)
                 .await
-                .map(|_| ())
+                .map(|_| notify(Change::Post(id)))
                 .context("Post::update")
         }
     );
 
+    def_fn!(
+        rerender_all(db) -> usize {
+            let count = Post::rerender(db).await?;
+            notify(Change::All);
+            Ok(count)
+        }
+    );
+
+    /// 迁移时在事务中调用
+    pub(crate) async fn rerender<C>(db: &C) -> anyhow::Result<usize>
+    where
+        C: ConnectionTrait,
+    {
+        let posts = Post::find().all(db).await.context("Post::rerender::find")?;
+        let count = posts.len();
+        for post in posts {
+            let mut active_model = ActiveModel {
+                id: ActiveValue::set(post.id),
+                ..Default::default()
+            };
+            Rendered::new(&post.content)?.set(&mut active_model);
+            active_model
+                .update(db)
+                .await
+                .context("Post::rerender")?;
+        }
+        Ok(count)
+    }
+
     def_fn!(
         reply(db, id: u32, new_comment: NewComment, reply_to: Option<u32>) -> u32 {
             Comment::insert(db, id, new_comment, reply_to).await.context("Post::reply")
@@ -157,6 +255,11 @@ impl Post {
 }
 
 impl PostModel {
+    /// 没有标题或者还没有重新渲染时为空
+    pub fn toc(&self) -> Vec<TocEntry> {
+        serde_json::from_str(&self.toc_json).unwrap_or_default()
+    }
+
     #[inline]
     pub async fn refresh(
         &mut self,
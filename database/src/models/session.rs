@@ -0,0 +1,93 @@
+use anyhow::Context;
+use chrono::NaiveDateTime;
+use sea_orm::{
+    ActiveModelBehavior, ColumnTrait, ConnectionTrait, DbBackend,
+    DeriveEntityModel, DerivePrimaryKey, DeriveRelation, EntityTrait,
+    EnumIter, IdenStatic, PrimaryKeyTrait, QueryFilter, Statement,
+};
+
+use super::def_fn;
+
+pub type SessionRecord = Entity;
+pub type SessionModel = Model;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[sea_orm(table_name = "sessions")]
+pub struct Model {
+    /// `async_session::Session::id`
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: String,
+    /// bincode序列化后的session
+    pub data: Vec<u8>,
+    /// utc时间, 为空则永不过期
+    #[sea_orm(nullable)]
+    pub expiry: Option<NaiveDateTime>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl SessionRecord {
+    def_fn!(
+        find_all(db) -> Vec<SessionModel> {
+            SessionRecord::find().all(db).await.context("SessionRecord::find_all")
+        }
+    );
+
+    def_fn!(
+        find_one(db, id: &str) -> Option<SessionModel> {
+            SessionRecord::find_by_id(id.to_owned())
+                .one(db)
+                .await
+                .context("SessionRecord::find_one")
+        }
+    );
+
+    def_fn!(
+        upsert(db, id: &str, data: Vec<u8>, expiry: Option<NaiveDateTime>) -> () {
+            db.execute(Statement::from_sql_and_values(
+                DbBackend::Sqlite,
+                r#"INSERT INTO "sessions" ("id", "data", "expiry") VALUES (?, ?, ?)
+                   ON CONFLICT ("id") DO UPDATE SET "data" = "excluded"."data", "expiry" = "excluded"."expiry""#,
+                vec![id.into(), data.into(), expiry.into()],
+            ))
+            .await
+            .map(|_| ())
+            .context("SessionRecord::upsert")
+        }
+    );
+
+    def_fn!(
+        delete(db, id: &str) -> () {
+            SessionRecord::delete_many()
+                .filter(Column::Id.eq(id))
+                .exec(db)
+                .await
+                .map(|_| ())
+                .context("SessionRecord::delete")
+        }
+    );
+
+    def_fn!(
+        delete_all(db) -> () {
+            SessionRecord::delete_many()
+                .exec(db)
+                .await
+                .map(|_| ())
+                .context("SessionRecord::delete_all")
+        }
+    );
+
+    def_fn!(
+        delete_expired(db, now: NaiveDateTime) -> u64 {
+            SessionRecord::delete_many()
+                .filter(Column::Expiry.lt(now))
+                .exec(db)
+                .await
+                .map(|res| res.rows_affected)
+                .context("SessionRecord::delete_expired")
+        }
+    );
+}
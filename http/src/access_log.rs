@@ -0,0 +1,134 @@
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::extract::ConnectInfo;
+use axum::http::Request;
+use chrono::Local;
+use compact_str::CompactString;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, CONTENT_LENGTH, REFERER, USER_AGENT};
+use hyper::service::Service;
+use hyper::Response;
+use logger::AccessRecord;
+use tower::Layer;
+
+use crate::client_info::ClientInfo;
+
+/// 每个请求写一条访问日志
+#[derive(Clone, Debug, Default)]
+pub struct AccessLogLayer;
+
+#[derive(Clone, Debug)]
+pub struct AccessLog<S> {
+    inner: S,
+}
+
+#[pin_project::pin_project]
+pub struct ResponseFuture<F> {
+    #[pin]
+    fut: F,
+    record: Option<AccessRecord>,
+    start: Instant,
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLog<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessLog { inner }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for AccessLog<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+    ResBody: HttpBody,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let headers = req.headers();
+        let peer = req
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        let record = AccessRecord {
+            time: Local::now(),
+            method: CompactString::new(req.method().as_str()),
+            path: req
+                .uri()
+                .path_and_query()
+                .map(|pq| pq.as_str())
+                .unwrap_or("/")
+                .to_owned(),
+            version: CompactString::new(format!("{:?}", req.version())),
+            status: 0,
+            latency_ms: 0.0,
+            bytes: None,
+            ip: ClientInfo::client_ip(headers, peer),
+            user_agent: header_str(headers, USER_AGENT),
+            referer: header_str(headers, REFERER),
+        };
+
+        ResponseFuture {
+            fut: self.inner.call(req),
+            record: Some(record),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+    B: HttpBody,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        let this = self.project();
+        let response: Response<B> = futures::ready!(this.fut.poll(cx))?;
+
+        if let Some(mut record) = this.record.take() {
+            record.status = response.status().as_u16();
+            record.latency_ms = this.start.elapsed().as_secs_f64() * 1000.0;
+            record.bytes = response.body().size_hint().exact().or_else(|| {
+                response
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|len| len.to_str().ok())
+                    .and_then(|len| len.parse().ok())
+            });
+            logger::access(record);
+        }
+
+        Poll::Ready(Ok(response))
+    }
+}
+
+#[inline]
+fn header_str(
+    headers: &HeaderMap,
+    name: hyper::header::HeaderName,
+) -> Option<CompactString> {
+    headers
+        .get(name)
+        .and_then(|val| val.to_str().ok())
+        .map(CompactString::new)
+}
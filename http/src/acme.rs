@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use std::fs::{read, read_to_string, rename, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+use axum::body::{Bytes, Full};
+use axum::extract::Path as PathParam;
+use axum::http::{Response, StatusCode};
+use compact_str::CompactString;
+use instant_acme::{
+    Account, AccountCredentials, AuthorizationStatus, ChallengeType,
+    Identifier, NewAccount, NewOrder, OrderStatus,
+};
+use once_cell::sync::Lazy;
+use rcgen::{Certificate, CertificateParams, DistinguishedName};
+
+use config::AcmeConfig;
+use timer::{Follow, Task};
+
+pub const CHALLENGE_PATH_PREFIX: &str = "/.well-known/acme-challenge/";
+
+const ACME_DIR_NAME: &str = "acme";
+const ACCOUNT_FILE_NAME: &str = "account.json";
+const CERT_FILE_NAME: &str = "cert.pem";
+const KEY_FILE_NAME: &str = "key.pem";
+
+/// 续期失败后的重试间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+
+static ISSUING: AtomicBool = AtomicBool::new(false);
+
+/// token -> key authorization, 只在签发期间存在
+static CHALLENGES: Lazy<RwLock<HashMap<CompactString, String>>> =
+    Lazy::new(Default::default);
+
+pub fn acme_dir(data_path: &Path) -> PathBuf {
+    data_path.join(ACME_DIR_NAME)
+}
+
+/// acme签发的证书和私钥的路径
+pub fn cert_paths(data_path: &Path) -> (PathBuf, PathBuf) {
+    let dir = acme_dir(data_path);
+    (dir.join(CERT_FILE_NAME), dir.join(KEY_FILE_NAME))
+}
+
+/// 查找http-01验证的响应内容, 同时供https路由和重定向服务使用
+pub fn challenge_response(token: &str) -> Option<String> {
+    CHALLENGES.read().unwrap().get(token).cloned()
+}
+
+pub async fn challenge(
+    PathParam(token): PathParam<String>,
+) -> Response<Full<Bytes>> {
+    match challenge_response(&token) {
+        Some(key_auth) => Response::builder()
+            .status(StatusCode::OK)
+            .body(Full::from(key_auth))
+            .unwrap(),
+        None => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Full::from(Bytes::new()))
+            .unwrap(),
+    }
+}
+
+/// 证书不存在或者即将过期时签发新的证书
+pub async fn ensure_cert(data_path: &Path) -> anyhow::Result<()> {
+    let config = acme_config()?;
+    let (cert, _) = cert_paths(data_path);
+    if renew_delay(&cert, &config).is_none() {
+        issue(data_path, &config).await?;
+    }
+    Ok(())
+}
+
+/// 在证书过期前`renew_before`时续期, 续期成功后证书由`CertResolver`自动重新加载.
+/// 签发要等待acme服务器, 在单独的task中进行, 时间轮的任务只负责触发,
+/// 不能阻塞其他定时任务
+pub fn schedule_renewal(data_path: &Path) {
+    let data_path = data_path.to_path_buf();
+    let (cert, _) = cert_paths(&data_path);
+    let delay = acme_config()
+        .ok()
+        .and_then(|config| renew_delay(&cert, &config))
+        .unwrap_or(RETRY_INTERVAL);
+    log::info!("next certificate renewal check in {:?}", delay);
+
+    global_resource::TIME_WHEEL.add_task(Task::interval(
+        move || {
+            let data_path = data_path.clone();
+            Box::pin(async move {
+                let (cert, _) = cert_paths(&data_path);
+                let next = match acme_config() {
+                    Ok(config) => match renew_delay(&cert, &config) {
+                        Some(next) => next,
+                        None => {
+                            spawn_issue(data_path, config);
+                            // 签发结束后再根据新的证书计算
+                            RETRY_INTERVAL
+                        }
+                    },
+                    Err(err) => {
+                        log::error!("failed to renew certificate: {:?}", err);
+                        RETRY_INTERVAL
+                    }
+                };
+                log::info!("next certificate renewal check in {:?}", next);
+                Follow::Change(next)
+            })
+        },
+        delay,
+    ));
+}
+
+/// 上一次签发还没有结束时不再重复签发
+fn spawn_issue(data_path: PathBuf, config: AcmeConfig) {
+    if ISSUING.swap(true, Ordering::AcqRel) {
+        return;
+    }
+    utils::task::spawn(
+        async move {
+            if let Err(err) = issue(&data_path, &config).await {
+                log::error!("failed to renew certificate: {:?}", err);
+            }
+            ISSUING.store(false, Ordering::Release);
+        },
+        "acme issue",
+    );
+}
+
+fn acme_config() -> anyhow::Result<AcmeConfig> {
+    config::get_config_temp()
+        .http()
+        .tls()
+        .as_ref()
+        .and_then(|tls| tls.acme().clone())
+        .context("`http.tls.acme` is not configured")
+}
+
+/// 距离需要续期还有多久, 证书不存在, 无法解析或者已经需要续期时返回`None`
+fn renew_delay(cert: &Path, config: &AcmeConfig) -> Option<Duration> {
+    let not_after = cert_expiry(cert)?;
+    not_after
+        .checked_sub(*config.renew_before().duration())?
+        .duration_since(SystemTime::now())
+        .ok()
+        .filter(|delay| !delay.is_zero())
+}
+
+fn cert_expiry(cert: &Path) -> Option<SystemTime> {
+    let data = read(cert).ok()?;
+    let (_, pem) = x509_parser::pem::parse_x509_pem(&data).ok()?;
+    let cert = pem.parse_x509().ok()?;
+    let timestamp = cert.validity().not_after.timestamp();
+    Some(UNIX_EPOCH + Duration::from_secs(timestamp.max(0) as u64))
+}
+
+async fn issue(data_path: &Path, config: &AcmeConfig) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        !config.domains().is_empty(),
+        "`http.tls.acme.domains` is empty"
+    );
+    let dir = acme_dir(data_path);
+    std::fs::create_dir_all(&dir)?;
+    log::info!("requesting certificate for {:?}", config.domains());
+
+    let account = load_or_create_account(&dir, config).await?;
+    let identifiers = config
+        .domains()
+        .iter()
+        .map(|domain| Identifier::Dns(domain.to_string()))
+        .collect::<Vec<_>>();
+    let (mut order, state) = account
+        .new_order(&NewOrder {
+            identifiers: &identifiers,
+        })
+        .await?;
+
+    let authorizations = order.authorizations(&state.authorizations).await?;
+    let mut tokens = Vec::new();
+    let res: anyhow::Result<String> = try {
+        for authz in &authorizations {
+            if let AuthorizationStatus::Valid = authz.status {
+                continue;
+            }
+            let challenge = authz
+                .challenges
+                .iter()
+                .find(|c| c.r#type == ChallengeType::Http01)
+                .context("no http-01 challenge offered")?;
+            let key_auth = order.key_authorization(challenge);
+            CHALLENGES.write().unwrap().insert(
+                CompactString::from(challenge.token.as_str()),
+                key_auth.as_str().to_owned(),
+            );
+            tokens.push(challenge.token.clone());
+            order.set_challenge_ready(&challenge.url).await?;
+        }
+
+        let state = wait_order_ready(&mut order).await?;
+
+        let mut params = CertificateParams::new(
+            config
+                .domains()
+                .iter()
+                .map(|domain| domain.to_string())
+                .collect::<Vec<_>>(),
+        );
+        params.distinguished_name = DistinguishedName::new();
+        let cert = Certificate::from_params(params)?;
+        let chain = order
+            .finalize(&cert.serialize_request_der()?, &state.finalize)
+            .await?;
+
+        write_file(&dir.join(KEY_FILE_NAME), &cert.serialize_private_key_pem())?;
+        chain
+    };
+
+    {
+        let mut challenges = CHALLENGES.write().unwrap();
+        tokens.iter().for_each(|token| {
+            challenges.remove(token.as_str());
+        });
+    }
+
+    // 最后写入证书, 让CertResolver重新加载时私钥已经就绪
+    write_file(&dir.join(CERT_FILE_NAME), &res?)?;
+    log::info!("certificate issued for {:?}", config.domains());
+    Ok(())
+}
+
+async fn wait_order_ready(
+    order: &mut instant_acme::Order,
+) -> anyhow::Result<instant_acme::OrderState> {
+    let mut delay = Duration::from_millis(500);
+    for _ in 0..10 {
+        tokio::time::sleep(delay).await;
+        let state = order.state().await?;
+        match state.status {
+            OrderStatus::Ready => return Ok(state),
+            OrderStatus::Invalid => {
+                anyhow::bail!("order is invalid: {:?}", state.error)
+            }
+            _ => delay = (delay * 2).min(Duration::from_secs(10)),
+        }
+    }
+    anyhow::bail!("timed out waiting for the order to become ready")
+}
+
+async fn load_or_create_account(
+    dir: &Path,
+    config: &AcmeConfig,
+) -> anyhow::Result<Account> {
+    let path = dir.join(ACCOUNT_FILE_NAME);
+    if path.exists() {
+        let data = read_to_string(&path)
+            .with_context(|| format!("read {}", path.display()))?;
+        let credentials =
+            serde_json::from_str::<AccountCredentials>(&data)?;
+        return Ok(Account::from_credentials(credentials)?);
+    }
+
+    let contact = config
+        .contact()
+        .iter()
+        .map(|c| c.as_str())
+        .collect::<Vec<_>>();
+    let account = Account::create(
+        &NewAccount {
+            contact: &contact,
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        config.directory(),
+    )
+    .await?;
+    write_file(&path, &serde_json::to_string(&account.credentials())?)?;
+    log::info!("acme account created at {}", config.directory());
+    Ok(account)
+}
+
+/// 先写入临时文件再重命名, 避免读到写了一半的文件
+fn write_file(path: &Path, content: &str) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut options = OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(&tmp)
+        .with_context(|| format!("write {}", tmp.display()))?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    rename(&tmp, path)?;
+    Ok(())
+}
@@ -0,0 +1,102 @@
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRequest, RequestParts};
+use axum::http::header::USER_AGENT;
+use axum::http::HeaderMap;
+use compact_str::CompactString;
+
+use crate::error::HttpError;
+
+/// 客户端的地址和UA
+///
+/// 只有在直连地址不可用(uds)或者来自本地回环(反向代理)时,
+/// 才会信任`X-Forwarded-For`和`X-Real-IP`.
+/// `X-Forwarded-For`中只有反向代理追加的最右边的地址可信, 左边的可以被客户端伪造
+pub struct ClientInfo {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<CompactString>,
+}
+
+impl ClientInfo {
+    /// `peer`是直连的地址, uds连接时为`None`
+    pub fn client_ip(
+        headers: &HeaderMap,
+        peer: Option<IpAddr>,
+    ) -> Option<IpAddr> {
+        match peer {
+            Some(ip) if !ip.is_loopback() => Some(ip),
+            _ => ClientInfo::forwarded_ip(headers).or(peer),
+        }
+    }
+
+    fn forwarded_ip(headers: &HeaderMap) -> Option<IpAddr> {
+        headers
+            .get("x-forwarded-for")
+            .and_then(|val| val.to_str().ok())
+            .and_then(|val| {
+                // 跳过本机上多层反向代理追加的回环地址
+                val.rsplit(',')
+                    .map(|ip| ip.trim().parse::<IpAddr>().ok())
+                    .find(|ip| ip.map_or(true, |ip| !ip.is_loopback()))
+            })
+            .unwrap_or_else(|| {
+                headers
+                    .get("x-real-ip")
+                    .and_then(|val| val.to_str().ok())
+                    .and_then(|ip| ip.trim().parse().ok())
+            })
+    }
+}
+
+#[async_trait::async_trait]
+impl<B> FromRequest<B> for ClientInfo
+where
+    B: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request(
+        req: &mut RequestParts<B>,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = req
+            .extensions()
+            .and_then(|ext| ext.get::<ConnectInfo<SocketAddr>>())
+            .map(|ConnectInfo(addr)| addr.ip());
+        let headers = req.headers().unwrap();
+
+        Ok(ClientInfo {
+            ip: ClientInfo::client_ip(headers, peer),
+            user_agent: headers
+                .get(USER_AGENT)
+                .and_then(|val| val.to_str().ok())
+                .map(CompactString::new),
+        })
+    }
+}
+
+#[test]
+fn forwarded_ip_test() {
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "1.1.1.1, 2.2.2.2".parse().unwrap());
+    assert_eq!(
+        ClientInfo::client_ip(&headers, None),
+        Some("2.2.2.2".parse().unwrap())
+    );
+
+    headers.insert(
+        "x-forwarded-for",
+        "1.1.1.1, 2.2.2.2, 127.0.0.1".parse().unwrap(),
+    );
+    assert_eq!(
+        ClientInfo::client_ip(&headers, Some([127, 0, 0, 1].into())),
+        Some("2.2.2.2".parse().unwrap())
+    );
+
+    headers.insert("x-forwarded-for", "1.1.1.1, bad".parse().unwrap());
+    assert_eq!(ClientInfo::client_ip(&headers, None), None);
+
+    assert_eq!(
+        ClientInfo::client_ip(&headers, Some([8, 8, 8, 8].into())),
+        Some([8, 8, 8, 8].into())
+    );
+}
@@ -0,0 +1,45 @@
+use std::sync::Arc;
+
+use sea_orm::DatabaseConnection;
+
+use database::models::comment::Comment;
+use timer::{Follow, Task};
+
+/// 定期彻底删除软删除超过`database.purge_deleted_comments_after`的评论,
+/// 为0时不删除, 但任务一直在, 之后修改配置也能生效
+pub fn regularly_purge(db: &Arc<DatabaseConnection>) {
+    let guard = config::get_config_temp();
+    let config = guard.database();
+
+    let db = Arc::clone(db);
+    global_resource::TIME_WHEEL.add_task(Task::interval(
+        move || {
+            log::debug!("checking for deleted comments to purge");
+
+            let db = Arc::clone(&db);
+            Box::pin(async move {
+                // 每次读取, 修改配置后下一次检查就会生效
+                let age = *config::get_config_temp()
+                    .database()
+                    .purge_deleted_comments_after()
+                    .duration();
+                if age.is_zero() {
+                    return Follow::Done;
+                }
+                let before = match chrono::Duration::from_std(age) {
+                    Ok(age) => chrono::Local::now().naive_local() - age,
+                    Err(_) => return Follow::Done,
+                };
+                match Comment::purge_deleted(&db, before).await {
+                    Ok(0) => {}
+                    Ok(count) => {
+                        log::info!("Purged {} deleted comments", count)
+                    }
+                    Err(err) => log::error!("regularly_purge: {:?}", err),
+                }
+                Follow::Done
+            })
+        },
+        *config.purge_check_interval().duration(),
+    ));
+}
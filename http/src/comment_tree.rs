@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+
+use config::CommentOrder;
+use database::models::comment::CommentModel;
+
+/// 评论和它的回复, 模板中用递归的partial展示
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct CommentNode {
+    #[serde(flatten)]
+    pub comment: CommentModel,
+    pub depth: u32,
+    /// 超过最大深度被拉平时, 记录回复的是谁
+    pub reply_to: Option<String>,
+    pub children: Vec<CommentNode>,
+}
+
+struct Builder {
+    children: HashMap<u32, Vec<CommentModel>>,
+    max_depth: u32,
+}
+
+/// 回复的评论不存在时作为顶层评论.
+/// 已删除的评论只有在还有回复时才保留, 并且不再带有内容
+pub fn build(
+    comments: Vec<CommentModel>,
+    max_depth: u32,
+    order: CommentOrder,
+    reply_order: CommentOrder,
+) -> Vec<CommentNode> {
+    let ids = comments.iter().map(|comment| comment.id).collect::<HashSet<_>>();
+    let mut roots = Vec::new();
+    let mut children = HashMap::<u32, Vec<CommentModel>>::new();
+    for comment in comments {
+        match comment.parent_id.filter(|id| *id != comment.id && ids.contains(id)) {
+            Some(parent_id) => children.entry(parent_id).or_default().push(comment),
+            None => roots.push(comment),
+        }
+    }
+    sort(&mut roots, order);
+    children.values_mut().for_each(|list| sort(list, reply_order));
+
+    let mut builder = Builder {
+        children,
+        max_depth,
+    };
+    let nodes = roots
+        .into_iter()
+        .flat_map(|comment| builder.node(comment, 0))
+        .collect();
+    prune(nodes)
+}
+
+fn sort(comments: &mut [CommentModel], order: CommentOrder) {
+    comments.sort_by_key(|comment| (comment.create_time, comment.id));
+    if order == CommentOrder::Newest {
+        comments.reverse();
+    }
+}
+
+impl Builder {
+    /// 返回评论本身, 到达最大深度时后面跟着拉平的回复
+    fn node(&mut self, comment: CommentModel, depth: u32) -> Vec<CommentNode> {
+        if depth < self.max_depth {
+            let replies = self.children.remove(&comment.id).unwrap_or_default();
+            let children = replies
+                .into_iter()
+                .flat_map(|reply| self.node(reply, depth + 1))
+                .collect();
+            return vec![CommentNode {
+                comment,
+                depth,
+                reply_to: None,
+                children,
+            }];
+        }
+
+        // 回复链可能很长, 拉平时不能递归
+        let mut nodes = Vec::new();
+        let mut stack = vec![(comment, None)];
+        while let Some((comment, reply_to)) = stack.pop() {
+            let nickname = (!comment.deleted).then(|| comment.nickname.clone());
+            let replies = self.children.remove(&comment.id).unwrap_or_default();
+            stack.extend(replies.into_iter().rev().map(|reply| (reply, nickname.clone())));
+            nodes.push(CommentNode {
+                comment,
+                depth,
+                reply_to,
+                children: Vec::new(),
+            });
+        }
+        nodes
+    }
+}
+
+fn prune(nodes: Vec<CommentNode>) -> Vec<CommentNode> {
+    nodes
+        .into_iter()
+        .filter_map(|mut node| {
+            node.children = prune(node.children);
+            if !node.comment.deleted {
+                return Some(node);
+            }
+            if node.children.is_empty() {
+                return None;
+            }
+            node.comment.content.clear();
+            node.comment.email.clear();
+            node.comment.nickname.clear();
+            Some(node)
+        })
+        .collect()
+}
+
+#[test]
+fn build_test() {
+    let time = chrono::NaiveDate::from_ymd(2022, 1, 1).and_hms(0, 0, 0);
+    let comment = |id: u32, parent_id: Option<u32>, deleted: bool| CommentModel {
+        id,
+        post_id: 1,
+        content: format!("content {}", id),
+        create_time: time + chrono::Duration::seconds(id as i64),
+        email: String::new(),
+        nickname: format!("user {}", id),
+        parent_id,
+        deleted,
+        deleted_time: deleted.then(|| time),
+    };
+    let comments = vec![
+        comment(1, None, false),
+        comment(2, Some(1), true),
+        comment(3, Some(2), false),
+        comment(4, Some(3), false),
+        comment(5, None, true),
+        comment(6, Some(42), false),
+    ];
+
+    let tree = build(comments, 2, CommentOrder::Newest, CommentOrder::Oldest);
+    // 5已删除且没有回复, 6回复的评论不存在
+    assert_eq!(tree.iter().map(|node| node.comment.id).collect::<Vec<_>>(), [6, 1]);
+
+    let deleted = &tree[1].children[0];
+    assert_eq!(deleted.comment.id, 2);
+    assert!(deleted.comment.content.is_empty());
+
+    // 4超过了最大深度, 和3并列
+    let flat = &deleted.children;
+    assert_eq!(flat.iter().map(|node| (node.comment.id, node.depth)).collect::<Vec<_>>(), [(3, 2), (4, 2)]);
+    assert_eq!(flat[1].reply_to.as_deref(), Some("user 3"));
+
+    // 很长的回复链拉平后不会递归
+    let chain = (1..=100_000).map(|id| comment(id, (id > 1).then(|| id - 1), false)).collect();
+    let tree = build(chain, 1, CommentOrder::Oldest, CommentOrder::Oldest);
+    assert_eq!(tree[0].children.len(), 99_999);
+    assert_eq!(tree[0].children[1].reply_to.as_deref(), Some("user 2"));
+}
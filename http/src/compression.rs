@@ -0,0 +1,306 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::sync::RwLock;
+use std::task::{Context, Poll};
+
+use axum::body::{box_body, BoxBody, Bytes, Full};
+use axum::http::{Method, Request, Response, StatusCode};
+use compact_str::CompactString;
+use futures::future::BoxFuture;
+use hyper::header::{
+    HeaderMap, HeaderValue, ACCEPT_ENCODING, CONTENT_ENCODING,
+    CONTENT_LENGTH, CONTENT_TYPE, VARY,
+};
+use hyper::body::HttpBody;
+use hyper::service::Service;
+use once_cell::sync::Lazy;
+use tower::Layer;
+
+use config::CompressionConfig;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Encoding {
+    Br,
+    Zstd,
+    Gzip,
+}
+
+impl Encoding {
+    /// 同样被客户端接受时的优先顺序
+    const PREFERRED: [Encoding; 3] =
+        [Encoding::Br, Encoding::Zstd, Encoding::Gzip];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Br => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    /// `best`为true时使用最高的压缩等级, 只适合压缩一次就缓存的内容
+    pub fn encode(self, data: &[u8], best: bool) -> std::io::Result<Vec<u8>> {
+        match self {
+            Encoding::Br => {
+                let mut writer = brotli::CompressorWriter::new(
+                    Vec::with_capacity(data.len() / 2),
+                    4096,
+                    if best { 11 } else { 5 },
+                    22,
+                );
+                writer.write_all(data)?;
+                Ok(writer.into_inner())
+            }
+            Encoding::Zstd => {
+                zstd::encode_all(data, if best { 19 } else { 3 })
+            }
+            Encoding::Gzip => {
+                let mut encoder = flate2::write::GzEncoder::new(
+                    Vec::with_capacity(data.len() / 2),
+                    if best {
+                        flate2::Compression::best()
+                    } else {
+                        flate2::Compression::default()
+                    },
+                );
+                encoder.write_all(data)?;
+                encoder.finish()
+            }
+        }
+    }
+}
+
+/// 根据`Accept-Encoding`选择编码, q=0表示明确拒绝
+pub fn negotiate(headers: &HeaderMap) -> Option<Encoding> {
+    let accept = headers.get(ACCEPT_ENCODING)?.to_str().ok()?;
+    let mut wildcard = None;
+    let mut accepted = Vec::new();
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let name = parts.next().unwrap_or_default().trim();
+        let q = parts
+            .find_map(|param| param.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if name == "*" {
+            wildcard = Some(q > 0.0);
+        } else {
+            accepted.push((CompactString::new(name.to_ascii_lowercase()), q > 0.0));
+        }
+    }
+
+    Encoding::PREFERRED.into_iter().find(|encoding| {
+        accepted
+            .iter()
+            .find(|(name, _)| name == encoding.as_str())
+            .map(|(_, ok)| *ok)
+            .or(wildcard)
+            .unwrap_or(false)
+    })
+}
+
+/// 内容类型是否在`http.compression.mime_types`中
+pub fn is_compressible(config: &CompressionConfig, content_type: &str) -> bool {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    config.mime_types().iter().any(|allowed| {
+        match allowed.strip_suffix("/*") {
+            Some(ty) => mime
+                .split_once('/')
+                .map(|(t, _)| t == ty)
+                .unwrap_or(false),
+            None => mime == allowed.as_str(),
+        }
+    })
+}
+
+/// 压缩很耗cpu, 放到blocking线程中, 不阻塞其他请求
+async fn encode_blocking(
+    encoding: Encoding,
+    data: Bytes,
+    best: bool,
+) -> std::io::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || encoding.encode(&data, best))
+        .await
+        .unwrap_or_else(|err| {
+            Err(std::io::Error::new(std::io::ErrorKind::Other, err))
+        })
+}
+
+/// 压缩过的静态文件, 以内容的hash判断文件是否变化
+static PRECOMPRESSED: Lazy<RwLock<HashMap<(String, Encoding), (u64, Bytes)>>> =
+    Lazy::new(Default::default);
+
+/// 静态文件的内容和hash, 内容没变时不用重新计算
+static ASSET_HASHES: Lazy<RwLock<HashMap<String, (Bytes, u64)>>> =
+    Lazy::new(Default::default);
+
+pub fn content_hash(data: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// 内嵌的文件每次都指向同一块内存, 本地文件比较内容也比计算hash快
+pub fn asset_hash(path: &str, data: &Bytes) -> u64 {
+    if let Some((cached, hash)) = ASSET_HASHES.read().unwrap().get(path) {
+        let same_memory =
+            cached.as_ptr() == data.as_ptr() && cached.len() == data.len();
+        if same_memory || cached == data {
+            return *hash;
+        }
+    }
+
+    let hash = content_hash(data);
+    ASSET_HASHES
+        .write()
+        .unwrap()
+        .insert(path.to_owned(), (data.clone(), hash));
+    hash
+}
+
+/// 静态文件只会以最高等级压缩一次, 之后直接使用缓存
+pub async fn precompressed(
+    path: &str,
+    data: Bytes,
+    hash: u64,
+    encoding: Encoding,
+) -> std::io::Result<Bytes> {
+    let key = (path.to_owned(), encoding);
+    if let Some((cached_hash, bytes)) = PRECOMPRESSED.read().unwrap().get(&key) {
+        if *cached_hash == hash {
+            return Ok(bytes.clone());
+        }
+    }
+
+    let bytes = Bytes::from(encode_blocking(encoding, data, true).await?);
+    PRECOMPRESSED
+        .write()
+        .unwrap()
+        .insert(key, (hash, bytes.clone()));
+    Ok(bytes)
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CompressionLayer;
+
+#[derive(Clone, Debug)]
+pub struct Compression<S> {
+    inner: S,
+}
+
+impl<S> Layer<S> for CompressionLayer {
+    type Service = Compression<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Compression { inner }
+    }
+}
+
+impl<S, ReqBody> Service<Request<ReqBody>> for Compression<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<BoxBody>>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    #[inline]
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let encoding = if req.method() == Method::HEAD {
+            None
+        } else {
+            negotiate(req.headers())
+        };
+        let fut = self.inner.call(req);
+        Box::pin(async move { Ok(compress(fut.await?, encoding).await) })
+    }
+}
+
+/// 只压缩大小已知的响应, 流式的响应原样返回
+async fn compress(
+    response: Response<BoxBody>,
+    encoding: Option<Encoding>,
+) -> Response<BoxBody> {
+    let config = config::get_config_temp().http().compression().clone();
+    let size = response.body().size_hint().exact();
+    let compressible = *config.enable()
+        && !response.headers().contains_key(CONTENT_ENCODING)
+        && !matches!(
+            response.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+        )
+        && size.map_or(false, |size| size >= config.min_size().get_bytes())
+        && response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|ty| ty.to_str().ok())
+            .map_or(false, |ty| is_compressible(&config, ty));
+    if !compressible {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .append(VARY, HeaderValue::from_static("accept-encoding"));
+    let encoding = match encoding {
+        Some(encoding) => encoding,
+        None => return Response::from_parts(parts, body),
+    };
+
+    let data = match hyper::body::to_bytes(body).await {
+        Ok(data) => data,
+        Err(err) => {
+            log::error!("failed to read response body: {}", err);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(box_body(Full::from(Bytes::new())))
+                .unwrap();
+        }
+    };
+
+    match encode_blocking(encoding, data.clone(), false).await {
+        Ok(compressed) => {
+            parts.headers.insert(
+                CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.as_str()),
+            );
+            parts.headers.remove(CONTENT_LENGTH);
+            Response::from_parts(parts, box_body(Full::from(compressed)))
+        }
+        Err(err) => {
+            log::error!("failed to compress response: {}", err);
+            Response::from_parts(parts, box_body(Full::from(data)))
+        }
+    }
+}
+
+#[test]
+fn negotiate_test() {
+    let mut headers = HeaderMap::new();
+    assert_eq!(negotiate(&headers), None);
+
+    headers.insert(ACCEPT_ENCODING, "gzip, deflate, br".parse().unwrap());
+    assert_eq!(negotiate(&headers), Some(Encoding::Br));
+
+    headers.insert(ACCEPT_ENCODING, "br;q=0, gzip;q=0.5".parse().unwrap());
+    assert_eq!(negotiate(&headers), Some(Encoding::Gzip));
+
+    headers.insert(ACCEPT_ENCODING, "*;q=0, identity".parse().unwrap());
+    assert_eq!(negotiate(&headers), None);
+}
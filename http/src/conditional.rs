@@ -0,0 +1,139 @@
+use std::fmt::Display;
+use std::time::SystemTime;
+
+use axum::body::{Bytes, Full};
+use axum::http::{HeaderMap, HeaderValue, Response, StatusCode};
+use chrono::{Local, NaiveDateTime, TimeZone};
+use headers::{ETag, HeaderMapExt, IfModifiedSince, IfNoneMatch, LastModified};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+
+use crate::error::HttpError;
+
+/// 对应`http.cache_control`中的各组策略
+#[derive(Debug, Copy, Clone)]
+pub enum RouteGroup {
+    Pages,
+    Api,
+    Assets,
+}
+
+impl RouteGroup {
+    fn cache_control(self) -> Option<HeaderValue> {
+        let guard = config::get_config_temp();
+        let config = guard.http().cache_control();
+        let value = match self {
+            RouteGroup::Pages => config.pages(),
+            RouteGroup::Api => config.api(),
+            RouteGroup::Assets => config.assets(),
+        };
+        HeaderValue::from_str(value).ok()
+    }
+}
+
+/// 用来判断客户端缓存是否还新鲜的ETag和Last-Modified
+#[derive(Clone)]
+pub struct Validator {
+    etag: Option<ETag>,
+    last_modified: Option<SystemTime>,
+    private: bool,
+}
+
+impl Validator {
+    /// 同一份数据可能以不同的压缩编码发送, 所以只用弱ETag
+    pub fn new<T: Display>(tag: T, last_modified: Option<NaiveDateTime>) -> Self {
+        Validator {
+            etag: format!("W/\"{}\"", tag).parse().ok(),
+            last_modified: last_modified
+                .and_then(|time| Local.from_local_datetime(&time).single())
+                .map(SystemTime::from),
+            private: false,
+        }
+    }
+
+    /// 登录后看到的页面不能被共享缓存保存
+    pub fn private(mut self, private: bool) -> Self {
+        self.private = private;
+        self
+    }
+
+    /// 优先比较`If-None-Match`, 没有时才看`If-Modified-Since`
+    pub fn is_fresh(&self, headers: &HeaderMap) -> bool {
+        if let Some(if_none_match) = headers.typed_get::<IfNoneMatch>() {
+            return self
+                .etag
+                .as_ref()
+                .map_or(false, |etag| !if_none_match.precondition_passes(etag));
+        }
+        match (headers.typed_get::<IfModifiedSince>(), self.last_modified) {
+            (Some(since), Some(last_modified)) => !since.is_modified(last_modified),
+            _ => false,
+        }
+    }
+
+    /// 缓存仍然新鲜时返回304, 否则调用`body`生成内容.
+    /// `render.dev_mode`下模板随时会变, 不做条件请求
+    pub fn respond<F>(
+        self,
+        headers: &HeaderMap,
+        group: RouteGroup,
+        content_type: &'static str,
+        body: F,
+    ) -> Result<Response<Full<Bytes>>, HttpError>
+    where
+        F: FnOnce() -> Result<Full<Bytes>, HttpError>,
+    {
+        let dev_mode = *config::get_config_temp().render().dev_mode();
+        let fresh = !dev_mode && self.is_fresh(headers);
+
+        let mut response = if fresh {
+            Response::builder()
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Full::from(Bytes::new()))?
+        } else {
+            Response::builder()
+                .status(StatusCode::OK)
+                .header(CONTENT_TYPE, content_type)
+                .body(body()?)?
+        };
+
+        let resp_headers = response.headers_mut();
+        if dev_mode {
+            resp_headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+            return Ok(response);
+        }
+        if let Some(etag) = self.etag {
+            resp_headers.typed_insert(etag);
+        }
+        if let Some(last_modified) = self.last_modified {
+            resp_headers.typed_insert(LastModified::from(last_modified));
+        }
+        let cache_control = if self.private {
+            Some(HeaderValue::from_static("private, no-cache"))
+        } else {
+            group.cache_control()
+        };
+        if let Some(cache_control) = cache_control {
+            resp_headers.insert(CACHE_CONTROL, cache_control);
+        }
+        Ok(response)
+    }
+}
+
+#[test]
+fn is_fresh_test() {
+    let time = chrono::NaiveDate::from_ymd(2022, 1, 1).and_hms(0, 0, 0);
+    let validator = Validator::new("post-1", Some(time));
+    let mut headers = HeaderMap::new();
+    assert!(!validator.is_fresh(&headers));
+
+    headers.typed_insert(IfModifiedSince::from(SystemTime::from(
+        Local.from_local_datetime(&time).unwrap(),
+    )));
+    assert!(validator.is_fresh(&headers));
+
+    // If-None-Match优先
+    headers.insert(hyper::header::IF_NONE_MATCH, HeaderValue::from_static("W/\"post-2\""));
+    assert!(!validator.is_fresh(&headers));
+    headers.insert(hyper::header::IF_NONE_MATCH, HeaderValue::from_static("W/\"post-1\""));
+    assert!(validator.is_fresh(&headers));
+}
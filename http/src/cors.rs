@@ -1,7 +1,9 @@
 use std::future::Future;
 use std::pin::Pin;
+use std::sync::Arc;
 use std::task::{Context, Poll};
 
+use arc_swap::ArcSwap;
 use axum::http::Request;
 use compact_str::CompactString;
 use hyper::header::{
@@ -11,8 +13,14 @@ use hyper::service::Service;
 use hyper::Response;
 use tower::Layer;
 
+/// 内部共享, 配置变化时通过`update`原地替换允许的源
 #[derive(Clone, Debug)]
 pub struct CorsLayer {
+    origins: Arc<ArcSwap<Origins>>,
+}
+
+#[derive(Debug)]
+struct Origins {
     origins: Vec<CompactString>,
     allow_all: bool,
 }
@@ -32,13 +40,25 @@ pub struct ResponseFuture<F> {
 
 impl CorsLayer {
     pub fn new(origins: Vec<CompactString>) -> Self {
+        CorsLayer {
+            origins: Arc::new(ArcSwap::from_pointee(Origins::new(origins))),
+        }
+    }
+
+    pub fn update(&self, origins: Vec<CompactString>) {
+        self.origins.store(Arc::new(Origins::new(origins)));
+    }
+}
+
+impl Origins {
+    fn new(origins: Vec<CompactString>) -> Self {
         if origins.contains(&CompactString::new_inline("*")) {
-            CorsLayer {
+            Origins {
                 origins: Vec::new(),
                 allow_all: true,
             }
         } else {
-            CorsLayer {
+            Origins {
                 origins,
                 allow_all: false,
             }
@@ -77,8 +97,9 @@ where
     fn call(&mut self, mut req: Request<ReqBody>) -> Self::Future {
         let headers = req.headers_mut();
 
+        let origins = self.layer.origins.load();
         let mut allow_origin = None;
-        if self.layer.allow_all {
+        if origins.allow_all {
             allow_origin = Some(HeaderValue::from_static("*"));
         } else {
             let origin = headers
@@ -87,7 +108,7 @@ where
                 .flatten()
                 .map(CompactString::new);
             if let Some(origin) = origin {
-                if self.layer.origins.contains(&origin) {
+                if origins.origins.contains(&origin) {
                     allow_origin =
                         Some(HeaderValue::from_str(&origin).unwrap());
                 }
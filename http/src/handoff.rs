@@ -0,0 +1,142 @@
+use std::net::SocketAddr;
+use std::os::unix::io::{FromRawFd, IntoRawFd, RawFd};
+use std::os::unix::net::UnixListener;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use once_cell::sync::Lazy;
+
+/// systemd传递的第一个fd, 之后的fd依次递增
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+static HANDOFF: AtomicBool = AtomicBool::new(false);
+
+/// 从systemd或者父进程继承的监听socket, 绑定时优先使用
+static INHERITED: Lazy<Mutex<Vec<Inherited>>> =
+    Lazy::new(|| Mutex::new(listen_fds()));
+
+enum Inherited {
+    Tcp(std::net::TcpListener),
+    Unix(UnixListener),
+}
+
+/// 正在把socket交给新进程, 此时不能删除uds文件
+pub fn in_progress() -> bool {
+    HANDOFF.load(Ordering::Acquire)
+}
+
+pub fn take_tcp(addr: SocketAddr) -> Option<std::net::TcpListener> {
+    let mut inherited = INHERITED.lock().unwrap();
+    let index = inherited.iter().position(|socket| match socket {
+        Inherited::Tcp(listener) => listener.local_addr().ok() == Some(addr),
+        Inherited::Unix(_) => false,
+    })?;
+    match inherited.remove(index) {
+        Inherited::Tcp(listener) => Some(listener),
+        Inherited::Unix(_) => unreachable!(),
+    }
+}
+
+pub fn take_unix(path: &Path) -> Option<UnixListener> {
+    let mut inherited = INHERITED.lock().unwrap();
+    let index = inherited.iter().position(|socket| match socket {
+        Inherited::Unix(listener) => listener
+            .local_addr()
+            .ok()
+            .and_then(|addr| addr.as_pathname().map(|p| p == path))
+            .unwrap_or(false),
+        Inherited::Tcp(_) => false,
+    })?;
+    match inherited.remove(index) {
+        Inherited::Unix(listener) => Some(listener),
+        Inherited::Tcp(_) => unreachable!(),
+    }
+}
+
+/// 按照systemd socket activation的约定读取`LISTEN_FDS`.
+/// 热升级时子进程不知道自己的pid, 所以没有`LISTEN_PID`时也接受
+fn listen_fds() -> Vec<Inherited> {
+    let pid_matched = std::env::var("LISTEN_PID")
+        .map(|pid| pid.parse::<u32>().ok() == Some(std::process::id()))
+        .unwrap_or(true);
+    let count = std::env::var("LISTEN_FDS")
+        .ok()
+        .and_then(|count| count.parse::<RawFd>().ok())
+        .filter(|_| pid_matched)
+        .unwrap_or(0);
+    ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"]
+        .iter()
+        .for_each(std::env::remove_var);
+
+    (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count)
+        .filter_map(|fd| {
+            unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+            let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+            if tcp.local_addr().is_ok() {
+                return Some(Inherited::Tcp(tcp));
+            }
+            let unix = unsafe { UnixListener::from_raw_fd(tcp.into_raw_fd()) };
+            if let Some(path) = unix
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(Path::to_path_buf))
+            {
+                log::debug!("inherited unix socket {}", path.display());
+                Some(Inherited::Unix(unix))
+            } else {
+                log::warn!("ignore unsupported inherited fd {}", fd);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 用相同的参数启动新的进程, 并把监听socket作为`LISTEN_FDS`传过去.
+/// 成功后当前进程应该通过`SHUTDOWN_NOTIFY`处理完剩余的请求后退出
+pub fn spawn_child(fds: &[RawFd]) -> anyhow::Result<u32> {
+    let count = fds.len();
+    let fds = fds.to_vec();
+    let mut tmp = vec![0; count];
+
+    let mut command = Command::new(std::env::current_exe()?);
+    command
+        .args(std::env::args_os().skip(1))
+        .env("LISTEN_FDS", count.to_string())
+        .env_remove("LISTEN_PID")
+        .env_remove("LISTEN_FDNAMES")
+        // 指向当前进程, 留着的话新进程不会发送watchdog通知
+        .env_remove("WATCHDOG_PID");
+    unsafe {
+        // fork之后只能调用async-signal-safe的函数, 所以不能分配内存.
+        // 先把fd复制到不会冲突的位置, 再依次放到3, 4, 5...
+        command.pre_exec(move || {
+            let high = SD_LISTEN_FDS_START + count as RawFd;
+            for (i, fd) in fds.iter().enumerate() {
+                let dup = libc::fcntl(*fd, libc::F_DUPFD, high);
+                if dup < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                tmp[i] = dup;
+            }
+            for (i, fd) in tmp.iter().enumerate() {
+                if libc::dup2(*fd, SD_LISTEN_FDS_START + i as RawFd) < 0 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                libc::close(*fd);
+            }
+            Ok(())
+        });
+    }
+
+    HANDOFF.store(true, Ordering::Release);
+    match command.spawn() {
+        Ok(child) => Ok(child.id()),
+        Err(err) => {
+            HANDOFF.store(false, Ordering::Release);
+            Err(err.into())
+        }
+    }
+}
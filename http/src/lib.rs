@@ -4,33 +4,57 @@
 
 use std::fs::{read_to_string, OpenOptions};
 use std::io::Write;
-use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
 use std::process::exit;
-use std::str::FromStr;
 use std::sync::Arc;
 
 use axum::handler::get;
 use axum::{AddExtensionLayer, Router};
-use cfg_if::cfg_if;
 use inquire::error::InquireError;
 use inquire::PasswordDisplayMode;
+use sea_orm::DatabaseConnection;
 
-use global_resource::SHUTDOWN_NOTIFY;
 use template::TemplateManager;
 
+use crate::access_log::AccessLogLayer;
+use crate::compression::CompressionLayer;
 use crate::cors::CorsLayer;
+use crate::listener::Listeners;
+use crate::metrics::{Metrics, MetricsLayer};
 use crate::routes::auth::Password;
-use crate::routes::{assets, auth, edit, index, post};
-use crate::session_store::SessionStore;
+use crate::routes::{assets, auth, edit, health, index, post};
+pub use crate::session_store::{rotate_key, SessionInfo, SessionStore};
 
+/// 监听socket已经交给了新进程, 新进程成为了systemd的主进程
+#[cfg(target_family = "unix")]
+pub use crate::handoff::in_progress as handed_over;
+
+#[cfg(not(target_family = "unix"))]
+pub fn handed_over() -> bool {
+    false
+}
+
+mod access_log;
+mod acme;
+mod client_info;
+mod comment_purge;
+mod comment_tree;
+mod compression;
+mod conditional;
 mod cookies;
 mod cors;
 mod error;
+#[cfg(target_family = "unix")]
+mod handoff;
+mod listener;
 mod login_status;
+mod metrics;
+mod page_cache;
 mod routes;
 mod session;
 mod session_store;
+mod set_header;
+mod tls;
 
 pub async fn run_http_server(
     no_password: bool,
@@ -44,6 +68,14 @@ pub async fn run_http_server(
         None
     };
 
+    // 迁移时可能重新渲染文章, 主题的shortcode要先注册
+    let template_manager = TemplateManager::new()?;
+    let db = Arc::new(database::new().await?);
+    page_cache::init(template_manager.generation());
+    comment_purge::regularly_purge(&db);
+
+    let cors = CorsLayer::new(config.cors().clone());
+    let request_metrics = Arc::new(Metrics::default());
     let axum_app = Router::new()
         .nest("/", index::routes())
         .nest("/post/:id", post::routes())
@@ -51,63 +83,120 @@ pub async fn run_http_server(
         .nest("/edit", edit::routes_post())
         .nest("/edit/comment", edit::routes_comment())
         .nest("/auth", auth::routes())
+        .route(
+            &format!("{}:token", acme::CHALLENGE_PATH_PREFIX),
+            get(acme::challenge),
+        )
+        .route("/highlight.css", get(assets::highlight_css))
+        .route("/metrics", get(metrics::metrics))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
         .layer(AddExtensionLayer::new(Arc::new(password)))
-        .layer(AddExtensionLayer::new(Arc::new(
-            TemplateManager::new()?,
-        )))
-        .layer(AddExtensionLayer::new(Arc::new(
-            database::new().await?,
-        )))
-        .layer(AddExtensionLayer::new(
-            SessionStore::new(
-                full_config.data_path().clone().join("sessions"),
-            )
-            .await?,
-        ))
-        .layer(CorsLayer::new(config.cors().clone()));
-
-    match config.r#type() {
-        config::ListenType::Uds => {cfg_if! {
-            if #[cfg(target_family = "unix")] {
-                use hyperlocal::UnixServerExt;
-                let path = Path::new(config.bind().as_str());
-                std::fs::remove_file(&path).ok();
-                let server = hyper::Server::bind_unix(&path)?
-                    .serve(axum_app.into_make_service());
-                log::info!("listen on unix://{}", path.display());
-                let graceful = server.with_graceful_shutdown(async {
-                    let resp = SHUTDOWN_NOTIFY.register(5).await.wait().await;
-                    std::fs::remove_file(&path).unwrap();
-                    log::debug!("http server shutdown");
-                    resp.ready()
-                });
-                graceful.await
-            } else {
-                panic!("Unsupported. You cannot use unix sockets on non-Unix systems.")
+        .layer(AddExtensionLayer::new(Arc::new(template_manager)))
+        .layer(AddExtensionLayer::new(Arc::clone(&db)))
+        .layer(AddExtensionLayer::new(open_session_store(&db).await?))
+        .layer(AddExtensionLayer::new(Arc::clone(&request_metrics)))
+        .layer(cors.clone())
+        .layer(CompressionLayer)
+        .layer(MetricsLayer::new(request_metrics))
+        .layer(AccessLogLayer)
+        .boxed();
+
+    let https_addr = config
+        .listen()
+        .iter()
+        .find(|listen| matches!(listen.r#type(), config::ListenType::Https))
+        .map(listener::socket_addr)
+        .transpose()?;
+    let tls_config = match https_addr {
+        Some(addr) => Some(tls::init(&full_config, addr).await?),
+        None => None,
+    };
+
+    let (listeners, mut exits) = Listeners::new(axum_app, tls_config);
+    listeners.start(config.listen())?;
+    watch_config(Arc::clone(&listeners), cors);
+    // 发送失败时systemd的`Type=notify`服务永远不会就绪
+    if let Err(err) = utils::sd_notify::ready() {
+        log::error!("failed to notify systemd of readiness: {}", err);
+    }
+
+    #[cfg(target_family = "unix")]
+    let mut upgrade = tokio::signal::unix::signal(
+        tokio::signal::unix::SignalKind::user_defined2(),
+    )?;
+
+    loop {
+        #[cfg(target_family = "unix")]
+        let upgrade_signal = upgrade.recv();
+        #[cfg(not(target_family = "unix"))]
+        let upgrade_signal = futures::future::pending::<Option<()>>();
+
+        tokio::select! {
+            exit = exits.recv() => {
+                let (id, res) = match exit {
+                    Some(exit) => exit,
+                    None => break,
+                };
+                // 因为配置变化而被停止的监听器已经不在列表中了
+                if !listeners.is_running(id) {
+                    if let Err(err) = res {
+                        log::error!("listener exited with error: {:?}", err);
+                    }
+                    continue;
+                }
+                // 配置变化后启动的监听器出错时只记录下来, 不影响其他监听器
+                if listeners.is_rebound(id) {
+                    if let Err(err) = res {
+                        log::error!("listener exited with error: {:?}", err);
+                    }
+                } else {
+                    res?;
+                }
+                if !listeners.remove(id) {
+                    break;
+                }
+            }
+            _ = upgrade_signal => {
+                // 返回后由core通过SHUTDOWN_NOTIFY关闭, 旧的监听器会处理完剩余的请求
+                #[cfg(target_family = "unix")]
+                match handoff::spawn_child(&listeners.fds()) {
+                    Ok(pid) => {
+                        log::info!("handed listeners over to process {}", pid);
+                        utils::sd_notify::notify(&format!("MAINPID={}", pid)).ok();
+                        break;
+                    }
+                    Err(err) => log::error!("failed to upgrade: {:?}", err),
+                };
             }
-        }},
-        config::ListenType::Http => {
-            let addr = SocketAddr::new(
-                IpAddr::from_str(config.bind())?,
-                *config.port(),
-            );
-            let server = hyper::Server::bind(&addr)
-                .serve(axum_app.into_make_service());
-
-            log::info!("listen on http://{}", server.local_addr());
-
-            let graceful = server.with_graceful_shutdown(async {
-                let resp = SHUTDOWN_NOTIFY.register(5).await.wait().await;
-                log::debug!("http server shutdown");
-                resp.ready()
-            });
-            graceful.await
         }
-    }.unwrap();
+    }
 
     Ok(())
 }
 
+/// 配置文件变化时重新绑定有变化的监听器并更新cors.
+/// hook在notify的线程上执行, 所以需要提前拿到tokio runtime的handle
+fn watch_config(listeners: Arc<Listeners>, cors: CorsLayer) {
+    let handle = tokio::runtime::Handle::current();
+    config::hook(Box::new(move || {
+        let config = config::get_config_full();
+        cors.update(config.http().cors().clone());
+        let listeners = Arc::clone(&listeners);
+        handle.spawn(async move {
+            listeners.rebind(config.http().listen()).await;
+        });
+    }));
+}
+
+/// 打开配置中的session store, 同时也供cli管理会话使用
+pub async fn open_session_store(
+    db: &Arc<DatabaseConnection>,
+) -> anyhow::Result<SessionStore> {
+    let data_path = config::get_config_full().data_path().clone();
+    SessionStore::open(&data_path, db).await
+}
+
 pub const PASSWORD_FILE_NAME: &str = ".password";
 
 fn require_password(data_path: &Path) -> anyhow::Result<Password> {
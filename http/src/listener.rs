@@ -0,0 +1,424 @@
+use std::borrow::Borrow;
+use std::net::{IpAddr, SocketAddr};
+#[cfg(target_family = "unix")]
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Context;
+use axum::routing::BoxRoute;
+use axum::Router;
+use cfg_if::cfg_if;
+use hyper::header::STRICT_TRANSPORT_SECURITY;
+use hyper::server::accept::Accept;
+use hyper::server::conn::AddrIncoming;
+use tokio::sync::{mpsc, oneshot};
+use tokio_rustls::rustls::ServerConfig;
+
+use config::{ListenConfig, ListenType};
+use global_resource::SHUTDOWN_NOTIFY;
+
+#[cfg(target_family = "unix")]
+use crate::handoff;
+use crate::set_header::SetHeaderLayer;
+use crate::tls;
+
+/// 已经绑定好的监听器, 绑定成功后才会停止旧的监听器
+pub enum Bound {
+    /// 继承来的uds由上一个进程或者systemd创建, 关闭时不删除文件
+    #[cfg(target_family = "unix")]
+    Uds {
+        path: std::path::PathBuf,
+        listener: tokio::net::UnixListener,
+        inherited: bool,
+    },
+    Http(std::net::TcpListener),
+    Https(tokio::net::TcpListener),
+}
+
+/// 管理所有监听器, 配置变化时只重新绑定有变化的监听器
+pub struct Listeners {
+    app: Router<BoxRoute>,
+    tls_config: Option<Arc<ServerConfig>>,
+    running: Mutex<Vec<Running>>,
+    next_id: Mutex<usize>,
+    exit_tx: mpsc::UnboundedSender<(usize, anyhow::Result<()>)>,
+}
+
+struct Running {
+    id: usize,
+    config: ListenConfig,
+    /// 配置变化后启动的, 出错时不影响整个服务
+    rebound: bool,
+    stop: oneshot::Sender<()>,
+    closed: oneshot::Receiver<()>,
+    #[cfg(target_family = "unix")]
+    fd: RawFd,
+}
+
+impl Listeners {
+    pub fn new(
+        app: Router<BoxRoute>,
+        tls_config: Option<Arc<ServerConfig>>,
+    ) -> (Arc<Self>, mpsc::UnboundedReceiver<(usize, anyhow::Result<()>)>)
+    {
+        let (exit_tx, exit_rx) = mpsc::unbounded_channel();
+        let listeners = Arc::new(Listeners {
+            app,
+            tls_config,
+            running: Mutex::new(Vec::new()),
+            next_id: Mutex::new(0),
+            exit_tx,
+        });
+        (listeners, exit_rx)
+    }
+
+    pub fn start(&self, configs: &[ListenConfig]) -> anyhow::Result<()> {
+        anyhow::ensure!(!configs.is_empty(), "no listener configured");
+        for config in configs {
+            let bound = bind(config)?;
+            self.spawn(config.clone(), bound, false);
+        }
+        Ok(())
+    }
+
+    /// 先启动新的监听器, 再让旧的监听器处理完正在进行的请求后关闭.
+    /// 如果新旧监听器使用同一个地址, 只能先关闭旧的, 等它不再监听后再绑定
+    pub async fn rebind(&self, configs: &[ListenConfig]) {
+        let (stale, added) = {
+            let mut running = self.running.lock().unwrap();
+            let (keep, stale): (Vec<_>, Vec<_>) =
+                running.drain(..).partition(|r| {
+                    configs.iter().any(|c| same_listen(c, &r.config))
+                });
+            *running = keep;
+            let added = configs
+                .iter()
+                .filter(|c| {
+                    !running.iter().any(|r| same_listen(c, &r.config))
+                })
+                .cloned()
+                .collect::<Vec<_>>();
+            (stale, added)
+        };
+
+        let (conflict, stale): (Vec<_>, Vec<_>) =
+            stale.into_iter().partition(|r| {
+                added.iter().any(|c| {
+                    c.bind() == r.config.bind()
+                        && effective_port(c) == effective_port(&r.config)
+                })
+            });
+        let closed =
+            conflict.into_iter().map(Running::stop).collect::<Vec<_>>();
+        for closed in closed {
+            closed.await.ok();
+        }
+
+        for config in added {
+            if matches!(config.r#type(), ListenType::Https)
+                && self.tls_config.is_none()
+            {
+                log::error!(
+                    "failed to listen on {}: `http.tls` is only loaded at startup, restart to enable https",
+                    describe(&config)
+                );
+                continue;
+            }
+            match bind(&config) {
+                Ok(bound) => self.spawn(config, bound, true),
+                Err(err) => log::error!(
+                    "failed to listen on {}: {:?}",
+                    describe(&config),
+                    err
+                ),
+            }
+        }
+        stale.into_iter().for_each(|running| {
+            running.stop();
+        });
+    }
+
+    /// 所有正在运行的监听器的fd, 用于热升级时传给子进程
+    #[cfg(target_family = "unix")]
+    pub fn fds(&self) -> Vec<RawFd> {
+        self.running.lock().unwrap().iter().map(|r| r.fd).collect()
+    }
+
+    pub fn is_running(&self, id: usize) -> bool {
+        self.running.lock().unwrap().iter().any(|r| r.id == id)
+    }
+
+    pub fn is_rebound(&self, id: usize) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|r| r.id == id && r.rebound)
+    }
+
+    /// 监听器退出后调用, 返回是否还有监听器在运行
+    pub fn remove(&self, id: usize) -> bool {
+        let mut running = self.running.lock().unwrap();
+        running.retain(|r| r.id != id);
+        !running.is_empty()
+    }
+
+    fn spawn(&self, config: ListenConfig, bound: Bound, rebound: bool) {
+        let id = {
+            let mut next_id = self.next_id.lock().unwrap();
+            *next_id += 1;
+            *next_id
+        };
+        #[cfg(target_family = "unix")]
+        let fd = bound.as_raw_fd();
+        let (stop, stop_rx) = oneshot::channel();
+        let (closed_tx, closed) = oneshot::channel();
+        let app = self.app.clone();
+        let tls_config = self.tls_config.clone();
+        let exit_tx = self.exit_tx.clone();
+        utils::task::spawn(
+            async move {
+                let res =
+                    serve(bound, app, tls_config, stop_rx, closed_tx).await;
+                exit_tx.send((id, res)).ok();
+            },
+            "http listener",
+        );
+        self.running.lock().unwrap().push(Running {
+            id,
+            config,
+            rebound,
+            stop,
+            closed,
+            #[cfg(target_family = "unix")]
+            fd,
+        });
+    }
+}
+
+impl Running {
+    /// 返回的receiver在监听socket关闭后完成
+    fn stop(self) -> oneshot::Receiver<()> {
+        log::info!("stop listening on {}", describe(&self.config));
+        self.stop.send(()).ok();
+        self.closed
+    }
+}
+
+/// 和监听socket一起被drop, 字段按顺序drop, 所以socket先关闭
+pub struct Closing<L> {
+    listener: L,
+    _closed: oneshot::Sender<()>,
+}
+
+impl<L> Borrow<L> for Closing<L> {
+    fn borrow(&self) -> &L {
+        &self.listener
+    }
+}
+
+fn same_listen(a: &ListenConfig, b: &ListenConfig) -> bool {
+    matches!(
+        (a.r#type(), b.r#type()),
+        (ListenType::Http, ListenType::Http)
+            | (ListenType::Https, ListenType::Https)
+            | (ListenType::Uds, ListenType::Uds)
+    ) && a.bind() == b.bind()
+        && effective_port(a) == effective_port(b)
+}
+
+/// 没有配置端口时使用协议的默认端口, uds没有端口
+fn effective_port(listen: &ListenConfig) -> Option<u16> {
+    match listen.r#type() {
+        ListenType::Uds => None,
+        ListenType::Http => Some(listen.port().unwrap_or(80)),
+        ListenType::Https => Some(listen.port().unwrap_or(443)),
+    }
+}
+
+fn describe(listen: &ListenConfig) -> String {
+    match listen.r#type() {
+        ListenType::Uds => format!("unix://{}", listen.bind()),
+        ListenType::Http => format!(
+            "http://{}:{}",
+            listen.bind(),
+            effective_port(listen).unwrap()
+        ),
+        ListenType::Https => format!(
+            "https://{}:{}",
+            listen.bind(),
+            effective_port(listen).unwrap()
+        ),
+    }
+}
+
+pub fn bind(listen: &ListenConfig) -> anyhow::Result<Bound> {
+    Ok(match listen.r#type() {
+        ListenType::Uds => {cfg_if! {
+            if #[cfg(target_family = "unix")] {
+                let path = std::path::PathBuf::from(listen.bind().as_str());
+                match handoff::take_unix(&path) {
+                    Some(listener) => {
+                        listener.set_nonblocking(true)?;
+                        Bound::Uds {
+                            listener: tokio::net::UnixListener::from_std(listener)?,
+                            path,
+                            inherited: true,
+                        }
+                    }
+                    None => {
+                        std::fs::remove_file(&path).ok();
+                        Bound::Uds {
+                            listener: tokio::net::UnixListener::bind(&path)?,
+                            path,
+                            inherited: false,
+                        }
+                    }
+                }
+            } else {
+                anyhow::bail!("Unsupported. You cannot use unix sockets on non-Unix systems.")
+            }
+        }}
+        ListenType::Http => Bound::Http(bind_tcp(socket_addr(listen)?)?),
+        ListenType::Https => {
+            let listener = bind_tcp(socket_addr(listen)?)?;
+            listener.set_nonblocking(true)?;
+            Bound::Https(tokio::net::TcpListener::from_std(listener)?)
+        }
+    })
+}
+
+fn bind_tcp(addr: SocketAddr) -> std::io::Result<std::net::TcpListener> {
+    #[cfg(target_family = "unix")]
+    if let Some(listener) = handoff::take_tcp(addr) {
+        log::debug!("use inherited socket {}", addr);
+        return Ok(listener);
+    }
+    std::net::TcpListener::bind(addr)
+}
+
+#[cfg(target_family = "unix")]
+impl AsRawFd for Bound {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Bound::Uds { listener, .. } => listener.as_raw_fd(),
+            Bound::Http(listener) => listener.as_raw_fd(),
+            Bound::Https(listener) => listener.as_raw_fd(),
+        }
+    }
+}
+
+/// 在一个地址上提供服务, 直到`SHUTDOWN_NOTIFY`通知关闭或者被`stop`停止
+pub async fn serve(
+    bound: Bound,
+    app: Router<BoxRoute>,
+    tls_config: Option<Arc<ServerConfig>>,
+    stop: oneshot::Receiver<()>,
+    closed: oneshot::Sender<()>,
+) -> anyhow::Result<()> {
+    match bound {
+        #[cfg(target_family = "unix")]
+        Bound::Uds {
+            path,
+            listener,
+            inherited,
+        } => {
+            log::info!("listen on unix://{}", path.display());
+            let listener = Closing {
+                listener,
+                _closed: closed,
+            };
+            let incoming = hyper::server::accept::poll_fn(move |cx| {
+                listener
+                    .listener
+                    .poll_accept(cx)
+                    .map(|res| Some(res.map(|(stream, _)| stream)))
+            });
+            hyper::Server::builder(incoming)
+                .serve(app.into_make_service())
+                .with_graceful_shutdown(async {
+                    shutdown_signal(stop).await;
+                    if !inherited && !handoff::in_progress() {
+                        std::fs::remove_file(&path).ok();
+                    }
+                    log::debug!("http server unix://{} shutdown", path.display());
+                })
+                .await?;
+        }
+        Bound::Http(listener) => {
+            listener.set_nonblocking(true)?;
+            let incoming = AddrIncoming::from_listener(
+                tokio::net::TcpListener::from_std(listener)?,
+            )?;
+            let addr = incoming.local_addr();
+            log::info!("listen on http://{}", addr);
+
+            let mut incoming = Closing {
+                listener: incoming,
+                _closed: closed,
+            };
+            let incoming = hyper::server::accept::poll_fn(move |cx| {
+                Pin::new(&mut incoming.listener).poll_accept(cx)
+            });
+            hyper::Server::builder(incoming)
+                .serve(
+                    app.into_make_service_with_connect_info::<SocketAddr, _>(
+                    ),
+                )
+                .with_graceful_shutdown(async {
+                    shutdown_signal(stop).await;
+                    log::debug!("http server http://{} shutdown", addr);
+                })
+                .await?;
+        }
+        Bound::Https(listener) => {
+            let tls_config =
+                tls_config.context("`http.tls` is required for https")?;
+            let addr = listener.local_addr()?;
+            log::info!("listen on https://{}", addr);
+
+            let listener = Closing {
+                listener,
+                _closed: closed,
+            };
+            // 只有通过https访问时才告诉浏览器以后都使用https
+            let hsts = config::get_config_temp()
+                .http()
+                .tls()
+                .as_ref()
+                .and_then(|tls| tls.hsts().as_ref())
+                .map(|max_age| tls::hsts_header(*max_age.duration()));
+            hyper::Server::builder(tls::incoming(listener, tls_config))
+                .serve(
+                    app.layer(SetHeaderLayer::new(
+                        STRICT_TRANSPORT_SECURITY,
+                        hsts,
+                    ))
+                    .into_make_service_with_connect_info::<SocketAddr, _>(),
+                )
+                .with_graceful_shutdown(async {
+                    shutdown_signal(stop).await;
+                    log::debug!("https server https://{} shutdown", addr);
+                })
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// 被`stop`停止时不再需要等待关闭的通知
+async fn shutdown_signal(stop: oneshot::Receiver<()>) {
+    let handle = SHUTDOWN_NOTIFY.register(5).await;
+    tokio::select! {
+        resp = handle.wait() => resp.ready(),
+        _ = stop => SHUTDOWN_NOTIFY.deregister(handle).await,
+    }
+}
+
+pub fn socket_addr(listen: &ListenConfig) -> anyhow::Result<SocketAddr> {
+    let port = effective_port(listen)
+        .ok_or_else(|| anyhow::anyhow!("unix socket has no port"))?;
+    Ok(SocketAddr::new(IpAddr::from_str(listen.bind())?, port))
+}
@@ -0,0 +1,362 @@
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use axum::body::{Bytes, Full};
+use axum::extract::{ConnectInfo, Extension, TypedHeader};
+use axum::http::{Request, Response, StatusCode};
+use compact_str::CompactString;
+use headers::authorization::Bearer;
+use headers::Authorization;
+use hyper::header::CONTENT_TYPE;
+use hyper::service::Service;
+use tower::Layer;
+
+use crate::acme;
+use crate::error::HttpError;
+use crate::session_store::SessionStore;
+
+/// 延迟直方图的上界, 单位秒
+const BUCKETS: [f64; 11] =
+    [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+#[derive(Default, Debug)]
+pub struct Metrics {
+    routes: Mutex<BTreeMap<(CompactString, CompactString), RouteStats>>,
+}
+
+#[derive(Default, Debug)]
+struct RouteStats {
+    statuses: BTreeMap<u16, u64>,
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Metrics {
+    fn observe(
+        &self,
+        route: CompactString,
+        method: CompactString,
+        status: StatusCode,
+        seconds: f64,
+    ) {
+        let mut routes = self.routes.lock().unwrap();
+        let stats = routes.entry((route, method)).or_default();
+        *stats.statuses.entry(status.as_u16()).or_default() += 1;
+        BUCKETS
+            .iter()
+            .zip(stats.buckets.iter_mut())
+            .filter(|(le, _)| seconds <= **le)
+            .for_each(|(_, count)| *count += 1);
+        stats.sum += seconds;
+        stats.count += 1;
+    }
+
+    fn render(&self, out: &mut String) {
+        let routes = self.routes.lock().unwrap();
+
+        out.push_str("# HELP maop_http_requests_total Total number of http requests.\n");
+        out.push_str("# TYPE maop_http_requests_total counter\n");
+        for ((route, method), stats) in routes.iter() {
+            for (status, count) in &stats.statuses {
+                writeln!(
+                    out,
+                    r#"maop_http_requests_total{{route="{}",method="{}",status="{}"}} {}"#,
+                    route, method, status, count
+                )
+                .ok();
+            }
+        }
+
+        out.push_str("# HELP maop_http_request_duration_seconds Http request latencies.\n");
+        out.push_str("# TYPE maop_http_request_duration_seconds histogram\n");
+        for ((route, method), stats) in routes.iter() {
+            let labels = format!(r#"route="{}",method="{}""#, route, method);
+            for (le, count) in BUCKETS.iter().zip(stats.buckets.iter()) {
+                writeln!(
+                    out,
+                    r#"maop_http_request_duration_seconds_bucket{{{},le="{}"}} {}"#,
+                    labels, le, count
+                )
+                .ok();
+            }
+            writeln!(
+                out,
+                r#"maop_http_request_duration_seconds_bucket{{{},le="+Inf"}} {}"#,
+                labels, stats.count
+            )
+            .ok();
+            writeln!(
+                out,
+                "maop_http_request_duration_seconds_sum{{{}}} {}",
+                labels, stats.sum
+            )
+            .ok();
+            writeln!(
+                out,
+                "maop_http_request_duration_seconds_count{{{}}} {}",
+                labels, stats.count
+            )
+            .ok();
+        }
+    }
+}
+
+/// 所有注册过的路由, 参数统一写成`:id`
+const ROUTES: [&str; 24] = [
+    "/",
+    "/api",
+    "/post/:id",
+    "/post/:id/api",
+    "/assets/*",
+    "/edit",
+    "/edit/api",
+    "/edit/:id",
+    "/edit/:id/api",
+    "/edit/comment",
+    "/edit/comment/:id",
+    "/edit/comment/:id/restore",
+    "/edit/comment/deleted",
+    "/edit/comment/deleted/api",
+    "/auth",
+    "/auth/api",
+    "/auth/logout",
+    "/auth/sessions",
+    "/auth/sessions/revoke",
+    "/.well-known/acme-challenge/:token",
+    "/highlight.css",
+    "/metrics",
+    "/healthz",
+    "/readyz",
+];
+
+/// 把路径还原成路由, 避免id之类的参数让标签无限增长.
+/// 不是已知路由的请求可能来自扫描器, 不管状态码是什么都记为`unmatched`
+fn normalize_route(path: &str) -> CompactString {
+    if path.starts_with("/assets/") {
+        return CompactString::new_inline("/assets/*");
+    }
+    if path.starts_with(acme::CHALLENGE_PATH_PREFIX) {
+        return CompactString::new("/.well-known/acme-challenge/:token");
+    }
+
+    let mut route = String::with_capacity(path.len());
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        route.push('/');
+        if segment.bytes().all(|b| b.is_ascii_digit()) {
+            route.push_str(":id");
+        } else {
+            route.push_str(segment);
+        }
+    }
+    if route.is_empty() {
+        route.push('/');
+    }
+    if ROUTES.contains(&route.as_str()) {
+        CompactString::from(route)
+    } else {
+        CompactString::new_inline("unmatched")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MetricsService<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+}
+
+#[pin_project::pin_project]
+pub struct ResponseFuture<F> {
+    #[pin]
+    fut: F,
+    metrics: Arc<Metrics>,
+    path: String,
+    method: CompactString,
+    start: Instant,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        MetricsLayer { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        MetricsService {
+            inner,
+            metrics: Arc::clone(&self.metrics),
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for MetricsService<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        let path = req.uri().path().to_owned();
+        let method = CompactString::new(req.method().as_str());
+        ResponseFuture {
+            fut: self.inner.call(req),
+            metrics: Arc::clone(&self.metrics),
+            path,
+            method,
+            start: Instant::now(),
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        let this = self.project();
+        let response: Response<B> = futures::ready!(this.fut.poll(cx))?;
+
+        this.metrics.observe(
+            normalize_route(this.path),
+            this.method.clone(),
+            response.status(),
+            this.start.elapsed().as_secs_f64(),
+        );
+
+        Poll::Ready(Ok(response))
+    }
+}
+
+/// 只允许`http.metrics.allow`中的地址, 开启了`allow_unix`时的uds,
+/// 或者带着正确token的请求访问
+pub async fn metrics(
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    bearer: Option<TypedHeader<Authorization<Bearer>>>,
+    Extension(metrics): Extension<Arc<Metrics>>,
+    Extension(store): Extension<SessionStore>,
+) -> Result<Response<Full<Bytes>>, HttpError> {
+    let config = config::get_config_full().http().metrics().clone();
+    if !config.enable() {
+        return Err(HttpError::from_const(StatusCode::NOT_FOUND, "not found"));
+    }
+
+    let token_matched = match (config.token(), &bearer) {
+        (Some(token), Some(TypedHeader(Authorization(bearer)))) => {
+            bearer.token() == token.as_str()
+        }
+        _ => false,
+    };
+    let addr_allowed = match connect_info {
+        Some(ConnectInfo(addr)) => config.allow().contains(&addr.ip()),
+        None => *config.allow_unix(),
+    };
+    if !token_matched && !addr_allowed {
+        return Err(HttpError::from_const(StatusCode::FORBIDDEN, "forbidden"));
+    }
+
+    let mut out = String::with_capacity(4096);
+    metrics.render(&mut out);
+
+    if let Some(stats) = database::pool_stats() {
+        out.push_str("# HELP maop_db_pool_connections Database pool connections.\n");
+        out.push_str("# TYPE maop_db_pool_connections gauge\n");
+        writeln!(
+            out,
+            r#"maop_db_pool_connections{{state="idle"}} {}"#,
+            stats.idle
+        )
+        .ok();
+        writeln!(
+            out,
+            r#"maop_db_pool_connections{{state="active"}} {}"#,
+            (stats.size as usize).saturating_sub(stats.idle)
+        )
+        .ok();
+        out.push_str("# TYPE maop_db_pool_max_connections gauge\n");
+        writeln!(out, "maop_db_pool_max_connections {}", stats.max).ok();
+    }
+
+    out.push_str("# HELP maop_db_slow_queries_total Queries slower than `database.warn_time`.\n");
+    out.push_str("# TYPE maop_db_slow_queries_total counter\n");
+    writeln!(out, "maop_db_slow_queries_total {}", logger::slow_query_count())
+        .ok();
+
+    // 每次抓取时统计, 出错或者cookie模式无法统计时为-1
+    let sessions = match store.count().await {
+        Ok(count) => count.map_or(-1, |count| count as i64),
+        Err(err) => {
+            log::warn!("failed to count sessions: {:?}", err);
+            -1
+        }
+    };
+    out.push_str("# HELP maop_sessions Sessions in the session store, -1 if they cannot be counted (cookie store).\n");
+    out.push_str("# TYPE maop_sessions gauge\n");
+    writeln!(out, "maop_sessions {}", sessions).ok();
+
+    out.push_str("# HELP maop_timer_tasks Tasks waiting in the time wheel.\n");
+    out.push_str("# TYPE maop_timer_tasks gauge\n");
+    writeln!(
+        out,
+        "maop_timer_tasks {}",
+        global_resource::TIME_WHEEL.task_count()
+    )
+    .ok();
+
+    out.push_str("# HELP maop_logger_queue Log records waiting to be written.\n");
+    out.push_str("# TYPE maop_logger_queue gauge\n");
+    writeln!(out, "maop_logger_queue {}", logger::queue_len()).ok();
+
+    out.push_str("# HELP maop_access_log_dropped_total Access log records dropped because the queue was full.\n");
+    out.push_str("# TYPE maop_access_log_dropped_total counter\n");
+    writeln!(
+        out,
+        "maop_access_log_dropped_total {}",
+        logger::dropped_access_count()
+    )
+    .ok();
+
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .header(CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Full::from(out))?)
+}
+
+#[test]
+fn normalize_route_test() {
+    assert_eq!(normalize_route("/"), "/");
+    assert_eq!(normalize_route("/post/42/api"), "/post/:id/api");
+    assert_eq!(normalize_route("/assets/css/a.css"), "/assets/*");
+    assert_eq!(normalize_route("/edit/comment/7/restore"), "/edit/comment/:id/restore");
+    assert_eq!(normalize_route("/wp-login.php"), "unmatched");
+    assert_eq!(normalize_route("/post/abc"), "unmatched");
+    assert_eq!(normalize_route("/auth/api/anything"), "unmatched");
+}
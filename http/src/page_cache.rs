@@ -0,0 +1,241 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use axum::body::{Bytes, Full};
+use axum::http::{HeaderMap, Response};
+use database::Change;
+use once_cell::sync::Lazy;
+
+use crate::conditional::{RouteGroup, Validator};
+use crate::error::HttpError;
+
+/// 渲染好的页面, 文章和评论变化时精确地失效
+pub static PAGE_CACHE: Lazy<PageCache> = Lazy::new(Default::default);
+
+/// 模板和影响页面的配置的指纹, 放进页面的ETag中,
+/// 更换主题或者修改配置后客户端的缓存也会失效
+static TEMPLATE_GENERATION: AtomicU64 = AtomicU64::new(0);
+static RENDER_GENERATION: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Page {
+    Index,
+    Post(u32),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct PageKey {
+    pub page: Page,
+    pub logged: bool,
+}
+
+#[derive(Clone)]
+pub struct CachedPage {
+    pub html: Bytes,
+    pub validator: Validator,
+    /// 通过`[[post:id]]`链接到的文章, 它们的标题变化时页面也要失效
+    pub links: Vec<u32>,
+}
+
+struct Entry {
+    page: CachedPage,
+    last_used: u64,
+}
+
+#[derive(Default)]
+pub struct PageCache {
+    inner: Mutex<Inner>,
+    /// 每次失效都会增加, 渲染期间发生过失效的结果不会被缓存
+    generation: AtomicU64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<PageKey, Entry>,
+    size: u64,
+    clock: u64,
+}
+
+pub fn init(template_generation: u64) {
+    TEMPLATE_GENERATION.store(template_generation, Ordering::Release);
+    update_render_generation();
+    database::hook(Box::new(|change| PAGE_CACHE.invalidate(change)));
+    // 站点信息之类的配置会被渲染进页面
+    config::hook(Box::new(|| {
+        update_render_generation();
+        PAGE_CACHE.clear();
+    }));
+}
+
+pub fn render_generation() -> u64 {
+    RENDER_GENERATION.load(Ordering::Acquire)
+}
+
+fn update_render_generation() {
+    let guard = config::get_config_temp();
+    let mut hasher = DefaultHasher::new();
+    TEMPLATE_GENERATION.load(Ordering::Acquire).hash(&mut hasher);
+    serde_json::to_string(&(guard.site(), guard.render()))
+        .unwrap_or_default()
+        .hash(&mut hasher);
+    RENDER_GENERATION.store(hasher.finish(), Ordering::Release);
+}
+
+fn capacity() -> Option<u64> {
+    let guard = config::get_config_temp();
+    let config = guard.render();
+    let capacity = config.page_cache().get_bytes();
+    (!*config.dev_mode() && capacity > 0).then(|| capacity)
+}
+
+impl PageCache {
+    pub fn get(&self, key: PageKey) -> Option<CachedPage> {
+        capacity()?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.clock += 1;
+        let clock = inner.clock;
+        let entry = inner.entries.get_mut(&key)?;
+        entry.last_used = clock;
+        Some(entry.page.clone())
+    }
+
+    /// 在读取数据之前调用, 插入时用来判断数据是否已经过期
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn insert(&self, key: PageKey, generation: u64, page: CachedPage) {
+        let capacity = match capacity() {
+            Some(capacity) => capacity,
+            None => return,
+        };
+        let len = page.html.len() as u64;
+        if len > capacity {
+            return;
+        }
+
+        let mut inner = self.inner.lock().unwrap();
+        // 持有锁时检查, 失效操作也需要这把锁
+        if generation != self.generation() {
+            return;
+        }
+        inner.clock += 1;
+        let last_used = inner.clock;
+        if let Some(old) = inner.entries.insert(key, Entry { page, last_used }) {
+            inner.size -= old.page.html.len() as u64;
+        }
+        inner.size += len;
+
+        // 页面数量不多, 直接找最久没用的淘汰
+        while inner.size > capacity {
+            let lru = match inner
+                .entries
+                .iter()
+                .filter(|(k, _)| **k != key)
+                .min_by_key(|(_, entry)| entry.last_used)
+            {
+                Some((k, _)) => *k,
+                None => break,
+            };
+            let removed = inner.entries.remove(&lru).unwrap();
+            inner.size -= removed.page.html.len() as u64;
+        }
+    }
+
+    pub fn invalidate(&self, change: Change) {
+        let mut inner = self.inner.lock().unwrap();
+        self.generation.fetch_add(1, Ordering::AcqRel);
+        match change {
+            // 首页列出了所有文章
+            Change::Post(id) => inner.entries.retain(|key, entry| {
+                key.page != Page::Index
+                    && key.page != Page::Post(id)
+                    && !entry.page.links.contains(&id)
+            }),
+            Change::Comment { post_id } => inner
+                .entries
+                .retain(|key, _| key.page != Page::Post(post_id)),
+            Change::All => inner.entries.clear(),
+        }
+        inner.size = inner
+            .entries
+            .values()
+            .map(|entry| entry.page.html.len() as u64)
+            .sum();
+    }
+
+    pub fn clear(&self) {
+        self.invalidate(Change::All);
+    }
+}
+
+/// 没有命中时调用`render`读取数据并渲染, 之后的请求直接使用缓存
+pub async fn cached_page<F, Fut>(
+    key: PageKey,
+    headers: &HeaderMap,
+    render: F,
+) -> Result<Response<Full<Bytes>>, HttpError>
+where
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = Result<CachedPage, HttpError>>,
+{
+    let page = match PAGE_CACHE.get(key) {
+        Some(page) => page,
+        None => {
+            let generation = PAGE_CACHE.generation();
+            let page = render().await?;
+            PAGE_CACHE.insert(key, generation, page.clone());
+            page
+        }
+    };
+    let CachedPage { html, validator, .. } = page;
+    validator.respond(
+        headers,
+        RouteGroup::Pages,
+        "text/html; charset=utf-8",
+        || Ok(Full::from(html)),
+    )
+}
+
+#[test]
+fn page_cache_test() {
+    config::init(vec![]).unwrap();
+    let cache = PageCache::default();
+    let page = |html: &'static str| CachedPage {
+        html: Bytes::from_static(html.as_bytes()),
+        validator: Validator::new(html, None),
+        links: vec![3],
+    };
+    let index = PageKey {
+        page: Page::Index,
+        logged: false,
+    };
+    let post = PageKey {
+        page: Page::Post(1),
+        logged: false,
+    };
+
+    cache.insert(index, cache.generation(), page("index"));
+    cache.insert(post, cache.generation(), page("post"));
+    assert!(cache.get(index).is_some());
+
+    cache.invalidate(Change::Comment { post_id: 1 });
+    assert!(cache.get(index).is_some());
+    assert!(cache.get(post).is_none());
+
+    // 链接到的文章变化了
+    cache.insert(post, cache.generation(), page("post"));
+    cache.invalidate(Change::Post(3));
+    assert!(cache.get(post).is_none());
+
+    // 读取数据后发生了变化, 不能缓存旧的结果
+    let generation = cache.generation();
+    cache.invalidate(Change::Post(2));
+    cache.insert(post, generation, page("post"));
+    assert!(cache.get(post).is_none());
+    assert!(cache.get(index).is_none());
+}
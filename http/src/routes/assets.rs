@@ -1,16 +1,82 @@
 use std::borrow::Cow;
+use std::path::Path;
 use std::sync::Arc;
 use axum::body::{Bytes, Full};
 use axum::extract::Extension;
-use axum::http::{Response, StatusCode, Uri};
+use axum::http::{HeaderMap, HeaderValue, Response, StatusCode, Uri};
+use hyper::header::{CONTENT_ENCODING, VARY};
+use crate::compression;
+use crate::conditional::{RouteGroup, Validator};
 use crate::error::HttpError;
 
 #[allow(clippy::needless_lifetimes)]
-pub async fn assets<'reg>(uri: Uri, Extension(tm): Extension<Arc<template::TemplateManager<'reg>>>) -> Result<Response<Full<Bytes>>, HttpError> {
+pub async fn assets<'reg>(uri: Uri, headers: HeaderMap, Extension(tm): Extension<Arc<template::TemplateManager<'reg>>>) -> Result<Response<Full<Bytes>>, HttpError> {
+    let path = uri.path().trim_start_matches('/');
     let resp = Response::builder();
-    Ok(if let Some(data) = tm.provider().0.get(uri.path().trim_start_matches('/')).await? {
-        resp.status(StatusCode::OK).body(Full::from(data))?
-    } else {
-        resp.status(StatusCode::NOT_FOUND).body(Full::from(Cow::Borrowed(&[] as &'static [u8])))?
-    })
+    let data = match tm.provider().0.get(path).await? {
+        Some(Cow::Borrowed(data)) => Bytes::from_static(data),
+        Some(Cow::Owned(data)) => Bytes::from(data),
+        None => return Ok(resp.status(StatusCode::NOT_FOUND).body(Full::from(Cow::Borrowed(&[] as &'static [u8])))?),
+    };
+
+    let content_type = content_type(path);
+    let hash = compression::asset_hash(path, &data);
+    let config = config::get_config_temp().http().compression().clone();
+    let compressible = *config.enable() && data.len() as u64 >= config.min_size().get_bytes() && compression::is_compressible(&config, content_type);
+
+    // 静态文件在这里压缩并缓存, 带上Content-Encoding后压缩层会跳过.
+    // 客户端的缓存还新鲜时不需要压缩
+    let validator = Validator::new(format_args!("{:x}", hash), None);
+    let encoding = compressible.then(|| compression::negotiate(&headers)).flatten().filter(|_| !validator.is_fresh(&headers));
+    let mut encoded = None;
+    let body = match encoding {
+        Some(encoding) => match compression::precompressed(path, data.clone(), hash, encoding).await {
+            Ok(compressed) => {
+                encoded = Some(encoding);
+                compressed
+            }
+            Err(err) => {
+                log::error!("failed to compress {}: {}", path, err);
+                data
+            }
+        },
+        None => data,
+    };
+    let mut resp = validator.respond(&headers, RouteGroup::Assets, content_type, || Ok(Full::from(body)))?;
+
+    if let Some(encoding) = encoded {
+        resp.headers_mut().insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+    }
+    if compressible {
+        resp.headers_mut().insert(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    Ok(resp)
+}
+
+fn content_type(path: &str) -> &'static str {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()).unwrap_or_default() {
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "application/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "html" | "htm" => "text/html; charset=utf-8",
+        "txt" => "text/plain; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `render.highlight.theme`对应的代码高亮样式
+pub async fn highlight_css(headers: HeaderMap) -> Result<Response<Full<Bytes>>, HttpError> {
+    let theme = config::get_config_temp().render().highlight().theme().clone();
+    let css = utils::markdown::highlight::css(&theme)?;
+    Validator::new(format_args!("{:x}", compression::content_hash(css.as_bytes())), None)
+        .respond(&headers, RouteGroup::Assets, "text/css; charset=utf-8", || Ok(Full::from(css)))
 }
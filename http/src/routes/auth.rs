@@ -16,10 +16,13 @@ use anyhow::Context;
 use config::SiteConfig;
 use utils::password_hash::password_verify;
 
+use crate::client_info::ClientInfo;
 use crate::error::HttpError;
-use crate::login_status::LoginStatus;
+use crate::login_status::{Logged, LoginStatus};
 use crate::session::Session;
-use crate::session_store::SessionStore;
+use crate::session_store::{
+    SessionInfo, SessionMeta, SessionStore, SESSION_META_KEY,
+};
 
 pub type Password = Option<String>;
 
@@ -27,7 +30,12 @@ pub fn routes() -> Router<BoxRoute> {
     let router = Router::new()
         .route("/", post(login).get(index_ssr))
         .route("/logout", post(logout))
-        .route("/api", get(index_api));
+        .route("/api", get(index_api))
+        .route(
+            "/sessions",
+            get(list_sessions).delete(revoke_all_sessions),
+        )
+        .route("/sessions/revoke", post(revoke_session));
 
     router.boxed()
 }
@@ -75,6 +83,7 @@ pub async fn login(
     Json(data): Json<LoginData>,
     Extension(password): Extension<Arc<Password>>,
     Extension(store): Extension<SessionStore>,
+    client: ClientInfo,
     mut session: Session,
 ) -> Result<Response<Full<Bytes>>, HttpError> {
     let mut resp = Response::builder();
@@ -88,6 +97,15 @@ pub async fn login(
             session
                 .insert("login_status", LoginStatus::Logged)
                 .unwrap();
+            session
+                .insert(
+                    SESSION_META_KEY,
+                    SessionMeta::new(
+                        client.ip.map(|ip| ip.to_string().into()),
+                        client.user_agent,
+                    ),
+                )
+                .unwrap();
             session.expire_in({
                 *config::get_config_temp().http().session_expiry().duration()
             });
@@ -127,9 +145,82 @@ pub async fn logout(
                 .destroy_session(session.into())
                 .await
                 .context("destroy session failed")?;
-            resp.status(StatusCode::OK)
+            // cookie store无法在服务端撤销session, 所以总是让浏览器删除cookie
+            resp.status(StatusCode::OK).header(
+                SET_COOKIE,
+                "session=; Path=/; Max-Age=0",
+            )
         }
     }
     .body(Full::from("{}"))
     .unwrap())
 }
+
+#[derive(serde::Serialize)]
+pub struct SessionListItem {
+    #[serde(flatten)]
+    info: SessionInfo,
+    current: bool,
+}
+
+async fn list_sessions(
+    _: Logged,
+    Extension(store): Extension<SessionStore>,
+    session: Session,
+) -> Result<Json<Vec<SessionListItem>>, HttpError> {
+    let sessions = store
+        .list_sessions()
+        .await
+        .context("failed to list sessions")?;
+    Ok(Json(
+        sessions
+            .iter()
+            .map(|s| SessionListItem {
+                info: SessionInfo::from(s),
+                current: s.id() == session.id(),
+            })
+            .collect(),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RevokeData {
+    id: String,
+}
+
+async fn revoke_session(
+    _: Logged,
+    Json(data): Json<RevokeData>,
+    Extension(store): Extension<SessionStore>,
+) -> Result<Response<Full<Bytes>>, HttpError> {
+    let resp = Response::builder();
+    let session = store
+        .find_session(&data.id)
+        .await
+        .context("failed to load session")?;
+
+    Ok(match session {
+        Some(session) => {
+            store
+                .destroy_session(session)
+                .await
+                .context("destroy session failed")?;
+            resp.status(StatusCode::OK)
+        }
+        None => resp.status(StatusCode::NOT_FOUND),
+    }
+    .body(Full::from("{}"))?)
+}
+
+async fn revoke_all_sessions(
+    _: Logged,
+    Extension(store): Extension<SessionStore>,
+) -> Result<Response<Full<Bytes>>, HttpError> {
+    store
+        .clear_store()
+        .await
+        .context("clear session store failed")?;
+    Ok(Response::builder()
+        .status(StatusCode::OK)
+        .body(Full::from("{}"))?)
+}
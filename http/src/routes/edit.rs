@@ -38,7 +38,10 @@ pub fn routes_post() -> Router<BoxRoute> {
 pub fn routes_comment() -> Router<BoxRoute> {
     let router = Router::new()
         .route("/", post(new_comment))
-        .route("/:id", delete(delete_comment));
+        .route("/:id", delete(delete_comment))
+        .route("/:id/restore", post(restore_comment))
+        .route("/deleted", get(index_ssr_deleted_comments))
+        .route("/deleted/api", get(index_api_deleted_comments));
 
     router.boxed()
 }
@@ -217,11 +220,18 @@ async fn new_comment(
     if data.nickname.is_empty() || data.email.is_empty() || data.content.is_empty() {
         return Err(HttpError::from_const(StatusCode::BAD_REQUEST, "Content cannot be empty"))
     }
+    if let Some(reply_to) = data.reply_to {
+        let parent = Comment::find_one(&*db, reply_to).await?;
+        if !parent.map_or(false, |parent| parent.post_id == data.post_id && !parent.deleted) {
+            return Err(HttpError::from_const(StatusCode::BAD_REQUEST, "Reply to an unknown comment"))
+        }
+    }
     let comment_id = Comment::insert(
         &*db,
         data.post_id,
         NewComment {
-            content: html_escape(&data.content),
+            // markdown原文, 展示时按评论的规则清理
+            content: data.content.to_string(),
             nickname: html_escape(&data.nickname.to_string()),
             email: html_escape(&data.email.to_string()),
         },
@@ -239,8 +249,88 @@ async fn delete_comment(
 ) -> Result<Json<CommentRes>, HttpError> {
     if params.get("hard").is_some() {
         Comment::hard_delete(&*db, comment_id).await?;
-    } else {
-        Comment::soft_delete(&*db, comment_id).await?;
+    } else if !Comment::soft_delete(&*db, comment_id).await? {
+        return Err(HttpError::from_const(StatusCode::NOT_FOUND, "comment not found"))
+    }
+    Ok(Json(CommentRes { id: comment_id }))
+}
+
+async fn restore_comment(
+    _: Logged,
+    extract::Path(comment_id): extract::Path<u32>,
+    Extension(db): Extension<Arc<DatabaseConnection>>,
+) -> Result<Json<CommentRes>, HttpError> {
+    if !Comment::restore(&*db, comment_id).await? {
+        return Err(HttpError::from_const(StatusCode::NOT_FOUND, "comment not found"))
     }
     Ok(Json(CommentRes { id: comment_id }))
 }
+
+#[allow(clippy::needless_lifetimes)]
+pub async fn index_ssr_deleted_comments<'reg>(
+    _: Logged,
+    data: DeletedCommentsData,
+    Extension(tm): Extension<Arc<template::TemplateManager<'reg>>>,
+) -> Result<Html<String>, HttpError> {
+    tm.render("deleted", &data).map(Html).map_err(Into::into)
+}
+
+pub async fn index_api_deleted_comments(
+    _: Logged,
+    data: DeletedCommentsData,
+) -> Result<Json<DeletedCommentsData>, HttpError> {
+    Ok(Json(data))
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DeletedComment {
+    comment: CommentModel,
+    /// 文章已经被删除时为`None`
+    post_title: Option<String>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct DeletedCommentsData {
+    site: SiteConfig,
+    comments: Vec<DeletedComment>,
+    /// 超过这个时间的评论会被彻底删除
+    purge_after: utils::unit::time_unit::TimeUnit,
+}
+
+#[async_trait::async_trait]
+impl FromRequest for DeletedCommentsData {
+    type Rejection = HttpError;
+
+    async fn from_request(
+        req: &mut RequestParts<Body>,
+    ) -> Result<Self, Self::Rejection> {
+        Logged::from_request(req).await?;
+        let Extension(db): Extension<Arc<DatabaseConnection>> =
+            Extension::from_request(req)
+                .await
+                .context("`DatabaseConnection` extension missing")?;
+        let config = config::get_config_full();
+
+        let comments = Comment::find_deleted(&*db).await?;
+        let mut post_ids = comments.iter().map(|comment| comment.post_id).collect::<Vec<_>>();
+        post_ids.sort_unstable();
+        post_ids.dedup();
+        let titles = Post::find_by_ids(&*db, post_ids)
+            .await?
+            .into_iter()
+            .map(|post| (post.id, post.title))
+            .collect::<HashMap<_, _>>();
+
+        Ok(DeletedCommentsData {
+            site: config.site().clone(),
+            comments: comments
+                .into_iter()
+                .map(|comment| DeletedComment {
+                    post_title: titles.get(&comment.post_id).cloned(),
+                    comment,
+                })
+                .collect(),
+            purge_after: *config.database().purge_deleted_comments_after(),
+        })
+    }
+}
@@ -0,0 +1,151 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_session::{Session, SessionStore as AsyncSessionStore};
+use axum::extract::Extension;
+use axum::http::StatusCode;
+use axum::Json;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+use serde_json::{json, Value};
+
+use global_resource::SHUTDOWN_NOTIFY;
+
+use crate::session_store::SessionStore;
+
+/// 渲染页面必须的模板
+const REQUIRED_TEMPLATES: [&str; 5] = ["index", "post", "edit", "auth", "deleted"];
+
+/// 只要能处理请求就算存活
+pub async fn healthz() -> &'static str {
+    "ok"
+}
+
+/// 依赖都可用时才接受流量, 关闭过程中返回503让负载均衡摘掉这个实例
+#[allow(clippy::needless_lifetimes)]
+pub async fn readyz<'reg>(
+    Extension(db): Extension<Arc<DatabaseConnection>>,
+    Extension(store): Extension<SessionStore>,
+    Extension(tm): Extension<Arc<template::TemplateManager<'reg>>>,
+) -> (StatusCode, Json<Value>) {
+    if SHUTDOWN_NOTIFY.is_notified() {
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "shutdown": true })),
+        );
+    }
+
+    let database = db
+        .execute(Statement::from_string(
+            DbBackend::Sqlite,
+            "SELECT 1".to_owned(),
+        ))
+        .await
+        .map(|_| ())
+        .map_err(anyhow::Error::from);
+    // cookie模式没有服务端的存储, 不需要检查
+    let session_store = if store.is_stateless() {
+        Ok(())
+    } else {
+        check_session_store(&store).await
+    };
+    let templates = REQUIRED_TEMPLATES
+        .iter()
+        .find(|name| !tm.hbs().has_template(name))
+        .map_or(Ok(()), |name| {
+            Err(anyhow::anyhow!("template `{}` is missing", name))
+        });
+
+    let ready =
+        database.is_ok() && session_store.is_ok() && templates.is_ok();
+    (
+        if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        },
+        Json(json!({
+            "database": status(database),
+            "session_store": status(session_store),
+            "templates": status(templates),
+        })),
+    )
+}
+
+/// 写入一个很快过期的session, 能读回来后再删掉
+async fn check_session_store(
+    store: &impl AsyncSessionStore,
+) -> anyhow::Result<()> {
+    let mut session = Session::new();
+    session.expire_in(Duration::from_secs(60));
+    let cookie = store
+        .store_session(session.clone())
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("generate session cookie"))?;
+    anyhow::ensure!(
+        store.load_session(cookie).await?.is_some(),
+        "the probe session was not stored"
+    );
+    store.destroy_session(session).await?;
+    Ok(())
+}
+
+/// 错误只写到日志中, 不返回给客户端
+fn status(res: anyhow::Result<()>) -> Value {
+    match res {
+        Ok(_) => json!("ok"),
+        Err(err) => {
+            log::warn!("readiness check failed: {:?}", err);
+            json!("unavailable")
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_session::{MemoryStore, Session, SessionStore};
+
+    use super::check_session_store;
+
+    /// 只能读不能写, 比如只读的redis副本
+    #[derive(Debug, Clone)]
+    struct ReadOnlyStore(MemoryStore);
+
+    #[async_trait::async_trait]
+    impl SessionStore for ReadOnlyStore {
+        async fn load_session(
+            &self,
+            cookie_value: String,
+        ) -> async_session::Result<Option<Session>> {
+            self.0.load_session(cookie_value).await
+        }
+
+        async fn store_session(
+            &self,
+            _session: Session,
+        ) -> async_session::Result<Option<String>> {
+            Err(anyhow::anyhow!("read only"))
+        }
+
+        async fn destroy_session(
+            &self,
+            _session: Session,
+        ) -> async_session::Result {
+            Err(anyhow::anyhow!("read only"))
+        }
+
+        async fn clear_store(&self) -> async_session::Result {
+            Err(anyhow::anyhow!("read only"))
+        }
+    }
+
+    #[tokio::test]
+    async fn check_session_store_test() {
+        let store = MemoryStore::new();
+        check_session_store(&store).await.unwrap();
+        assert_eq!(store.count().await, 0);
+
+        assert!(check_session_store(&ReadOnlyStore(MemoryStore::new()))
+            .await
+            .is_err());
+    }
+}
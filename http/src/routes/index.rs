@@ -1,19 +1,22 @@
 use std::sync::Arc;
 
-use axum::body::Body;
+use axum::body::{Body, Bytes, Full};
 use axum::extract::{Extension, FromRequest, RequestParts};
 use axum::handler::get;
-use axum::response::Html;
+use axum::http::{HeaderMap, Response};
 use axum::routing::BoxRoute;
-use axum::{Json, Router};
+use axum::Router;
 use sea_orm::DatabaseConnection;
 use anyhow::Context;
 
 use config::SiteConfig;
 use database::models::post::{Post, PostModel};
 
+use crate::conditional::{RouteGroup, Validator};
 use crate::error::HttpError;
 use crate::login_status::LoginStatus;
+use crate::page_cache::{self, cached_page, CachedPage, Page, PageKey};
+use crate::routes::post::resolve_links;
 
 pub fn routes() -> Router<BoxRoute> {
     let router = Router::new().route("/", get(index_ssr))
@@ -24,14 +27,42 @@ pub fn routes() -> Router<BoxRoute> {
 
 #[allow(clippy::needless_lifetimes)]
 pub async fn index_ssr<'a>(
-    data: Data,
+    headers: HeaderMap,
+    login_status: LoginStatus,
+    Extension(db): Extension<Arc<DatabaseConnection>>,
     Extension(tm): Extension<Arc<template::TemplateManager<'a>>>,
-) -> Result<Html<String>, HttpError> {
-    tm.render("index", &data).map(Html).map_err(Into::into)
+) -> Result<Response<Full<Bytes>>, HttpError> {
+    let logged = matches!(login_status, LoginStatus::Logged);
+    let key = PageKey {
+        page: Page::Index,
+        logged,
+    };
+    cached_page(key, &headers, || async move {
+        let data = Data::load(&db, logged).await?;
+        Ok(CachedPage {
+            html: Bytes::from(tm.render("index", &data)?),
+            validator: data.validator(),
+            // 任何文章变化都会让首页失效
+            links: Vec::new(),
+        })
+    })
+    .await
 }
 
-pub async fn index_api(data: Data) -> Result<Json<Data>, HttpError> {
-    Ok(Json(data))
+pub async fn index_api(
+    headers: HeaderMap,
+    data: Data,
+) -> Result<Response<Full<Bytes>>, HttpError> {
+    data.validator().respond(
+        &headers,
+        RouteGroup::Api,
+        "application/json",
+        || {
+            Ok(Full::from(
+                serde_json::to_vec(&data).context("serialize index")?,
+            ))
+        },
+    )
 }
 
 ///todo: 分页
@@ -42,6 +73,49 @@ pub struct Data {
     posts: Vec<PostModel>,
 }
 
+impl Data {
+    async fn load(
+        db: &DatabaseConnection,
+        logged: bool,
+    ) -> Result<Self, HttpError> {
+        let mut posts = Post::find_all(db).await?;
+        let excerpts = posts
+            .iter()
+            .map(|post| resolve_links(&post.excerpt_html, &posts))
+            .collect::<Vec<_>>();
+        posts
+            .iter_mut()
+            .zip(excerpts)
+            .for_each(|(post, excerpt)| post.excerpt_html = excerpt);
+
+        Ok(Data {
+            site: config::get_config_temp().site().clone(),
+            logged,
+            posts,
+        })
+    }
+
+    /// 文章数量一起放进ETag, 删除文章时最新的修改时间不一定会变
+    fn validator(&self) -> Validator {
+        let newest = self
+            .posts
+            .iter()
+            .map(|post| post.last_modified_time)
+            .max();
+        Validator::new(
+            format_args!(
+                "index-{}-{}-{}-{:x}",
+                self.posts.len(),
+                newest.map(|time| time.timestamp()).unwrap_or_default(),
+                self.logged as u8,
+                page_cache::render_generation()
+            ),
+            newest,
+        )
+        .private(self.logged)
+    }
+}
+
 #[async_trait::async_trait]
 impl FromRequest for Data {
     type Rejection = HttpError;
@@ -54,11 +128,6 @@ impl FromRequest for Data {
             Extension::<Arc<DatabaseConnection>>::from_request(req)
                 .await
                 .context("`DatabaseConnection` extension missing")?;
-        let site = config::get_config_temp().site().clone();
-        Ok(Data {
-            site,
-            logged: matches!(login_status, LoginStatus::Logged),
-            posts: Post::find_all(&db).await?,
-        })
+        Data::load(&db, matches!(login_status, LoginStatus::Logged)).await
     }
 }
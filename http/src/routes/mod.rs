@@ -1 +1 @@
-utils::pub_mods!(index, auth, post, assets, edit);
+utils::pub_mods!(index, auth, post, assets, edit, health);
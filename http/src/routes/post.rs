@@ -1,22 +1,26 @@
-use std::collections::BTreeMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
 use anyhow::Context;
-use axum::body::Body;
+use axum::body::{Body, Bytes, Full};
 use axum::extract::{Extension, FromRequest, RequestParts};
 use axum::handler::get;
-use axum::http::StatusCode;
-use axum::response::Html;
+use axum::http::{HeaderMap, Response, StatusCode};
 use axum::routing::BoxRoute;
-use axum::{extract, Json, Router};
+use axum::{extract, Router};
+use chrono::NaiveDateTime;
 use sea_orm::DatabaseConnection;
 
 use config::SiteConfig;
-use database::models::comment::CommentModel;
 use database::models::post::{Post, PostModel};
+use utils::markdown::toc::TocEntry;
 
+use crate::comment_tree::{self, CommentNode};
+use crate::conditional::{RouteGroup, Validator};
 use crate::error::HttpError;
 use crate::login_status::LoginStatus;
+use crate::page_cache::{self, cached_page, CachedPage, Page, PageKey};
 
 pub fn routes() -> Router<BoxRoute> {
     let router = Router::new()
@@ -28,14 +32,42 @@ pub fn routes() -> Router<BoxRoute> {
 
 #[allow(clippy::needless_lifetimes)]
 pub async fn index_ssr<'reg>(
-    data: Data,
+    headers: HeaderMap,
+    login_status: LoginStatus,
+    extract::Path(post_id): extract::Path<u32>,
+    Extension(db): Extension<Arc<DatabaseConnection>>,
     Extension(tm): Extension<Arc<template::TemplateManager<'reg>>>,
-) -> Result<Html<String>, HttpError> {
-    tm.render("post", &data).map(Html).map_err(Into::into)
+) -> Result<Response<Full<Bytes>>, HttpError> {
+    let logged = matches!(login_status, LoginStatus::Logged);
+    let key = PageKey {
+        page: Page::Post(post_id),
+        logged,
+    };
+    cached_page(key, &headers, || async move {
+        let data = Data::load(&db, post_id, logged).await?;
+        Ok(CachedPage {
+            html: Bytes::from(tm.render("post", &data)?),
+            validator: data.validator(),
+            links: data.links.iter().map(|(id, _)| *id).collect(),
+        })
+    })
+    .await
 }
 
-pub async fn index_api(data: Data) -> Result<Json<Data>, HttpError> {
-    Ok(Json(data))
+pub async fn index_api(
+    headers: HeaderMap,
+    data: Data,
+) -> Result<Response<Full<Bytes>>, HttpError> {
+    data.validator().respond(
+        &headers,
+        RouteGroup::Api,
+        "application/json",
+        || {
+            Ok(Full::from(
+                serde_json::to_vec(&data).context("serialize post")?,
+            ))
+        },
+    )
 }
 
 ///todo: 分页
@@ -44,7 +76,111 @@ pub struct Data {
     site: SiteConfig,
     logged: bool,
     post: PostModel,
-    comments: BTreeMap<u32, CommentModel>,
+    toc: Vec<TocEntry>,
+    comments: Vec<CommentNode>,
+    /// 未删除的评论数量
+    comment_count: usize,
+    #[serde(skip)]
+    last_comment_time: Option<NaiveDateTime>,
+    /// 链接到的所有文章和它们的修改时间, 不存在的文章没有时间,
+    /// 之后创建时页面也要失效
+    #[serde(skip)]
+    links: Vec<(u32, Option<NaiveDateTime>)>,
+}
+
+/// 把`[[post:id]]`换成文章当前的地址和标题
+pub(crate) fn resolve_links(html: &str, posts: &[PostModel]) -> String {
+    utils::markdown::link::resolve(html, |id| {
+        posts
+            .iter()
+            .find(|post| post.id == id)
+            .map(|post| (format!("/post/{}", post.id), post.title.clone()))
+    })
+}
+
+impl Data {
+    async fn load(
+        db: &DatabaseConnection,
+        post_id: u32,
+        logged: bool,
+    ) -> Result<Self, HttpError> {
+        let post_and_comments = Post::find_and_commit(db, post_id)
+            .await?
+            .ok_or_else(|| {
+                HttpError::from_const(
+                    StatusCode::NOT_FOUND,
+                    "post not found",
+                )
+            })?;
+
+        let (mut post, comments) = post_and_comments;
+        let comment_count = comments.iter().filter(|comment| !comment.deleted).count();
+        let last_comment_time = comments.iter().map(|comment| comment.create_time).max();
+        let comments = {
+            let guard = config::get_config_temp();
+            let config = guard.render().comments();
+            comment_tree::build(
+                comments,
+                *config.max_depth(),
+                *config.order(),
+                *config.reply_order(),
+            )
+        };
+        let ids = utils::markdown::link::post_ids(&post.content_html);
+        let linked = if ids.is_empty() {
+            Vec::new()
+        } else {
+            Post::find_by_ids(db, ids.clone()).await?
+        };
+        post.content_html = resolve_links(&post.content_html, &linked);
+
+        Ok(Data {
+            site: config::get_config_temp().site().clone(),
+            logged,
+            comments,
+            comment_count,
+            last_comment_time,
+            toc: post.toc(),
+            links: ids
+                .into_iter()
+                .map(|id| {
+                    let time = linked
+                        .iter()
+                        .find(|post| post.id == id)
+                        .map(|post| post.last_modified_time);
+                    (id, time)
+                })
+                .collect(),
+            post,
+        })
+    }
+
+    /// 新的评论和链接到的文章也会改变页面, 所以最后修改时间取其中最新的
+    fn validator(&self) -> Validator {
+        let linked = self.links.iter().filter_map(|(_, time)| *time).max();
+        // 链接的文章出现或消失时也要变化
+        let mut links = DefaultHasher::new();
+        self.links.hash(&mut links);
+        let last_modified = self
+            .last_comment_time
+            .into_iter()
+            .chain(linked)
+            .chain(std::iter::once(self.post.last_modified_time))
+            .max();
+        Validator::new(
+            format_args!(
+                "post-{}-{}-{}-{:x}-{}-{:x}",
+                self.post.id,
+                self.post.last_modified_time.timestamp(),
+                self.comment_count,
+                links.finish(),
+                self.logged as u8,
+                page_cache::render_generation()
+            ),
+            last_modified,
+        )
+        .private(self.logged)
+    }
 }
 
 #[async_trait::async_trait]
@@ -61,26 +197,11 @@ impl FromRequest for Data {
             Extension::<Arc<DatabaseConnection>>::from_request(req)
                 .await
                 .context("`DatabaseConnection` extension missing")?;
-        let site = config::get_config_temp().site().clone();
-
-        let post_and_comments = Post::find_and_commit(&*db, post_id)
-            .await?
-            .ok_or_else(|| {
-                HttpError::from_const(
-                    StatusCode::NOT_FOUND,
-                    "post not found",
-                )
-            })?;
-
-        Ok(Data {
-            site,
-            logged: matches!(login_status, LoginStatus::Logged),
-            comments: post_and_comments
-                .1
-                .into_iter()
-                .map(|comment| (comment.id, comment))
-                .collect(),
-            post: post_and_comments.0,
-        })
+        Data::load(
+            &db,
+            post_id,
+            matches!(login_status, LoginStatus::Logged),
+        )
+        .await
     }
 }
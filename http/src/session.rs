@@ -1,11 +1,18 @@
+use crate::client_info::ClientInfo;
 use crate::cookies::Cookies;
 use crate::error::HttpError;
-use crate::session_store::SessionStore;
+use crate::session_store::{
+    SessionMeta, SessionStore, SESSION_META_KEY,
+};
 use anyhow::Context;
 use async_session::SessionStore as _;
 use axum::extract::{Extension, FromRequest, RequestParts};
+use chrono::{Duration, Utc};
 use std::ops::{Deref, DerefMut};
 
+/// 距离上次更新`last_seen`超过这个时间才会重新写入store
+const TOUCH_INTERVAL_SECS: i64 = 60;
+
 pub struct Session(async_session::Session);
 
 #[async_trait::async_trait]
@@ -27,19 +34,64 @@ where
             .0
             .map(|c| c.get("session").map(|s| s.to_owned()))
             .flatten();
-        Ok(Session(
-            if let Some(cookie) = cookie {
-                store
-                    .load_session(cookie)
-                    .await
-                    .context("Failed to parse the session. Please clear the cookies")?
-                    .map(|s| s.validate())
-            } else {
-                None
+        let session = if let Some(cookie) = cookie {
+            store
+                .load_session(cookie)
+                .await
+                .context("Failed to parse the session. Please clear the cookies")?
+                .map(|s| s.validate())
+        } else {
+            None
+        }
+        .flatten();
+
+        Ok(Session(match session {
+            Some(mut session) if !store.is_stateless() => {
+                let client = ClientInfo::from_request(req).await?;
+                if Session::touch(&mut session, client) {
+                    store
+                        .store_session(session.clone())
+                        .await
+                        .context("failed to store session")?;
+                }
+                session
             }
-            .flatten()
-            .unwrap_or_default(),
-        ))
+            Some(session) => session,
+            None => Default::default(),
+        }))
+    }
+}
+
+impl Session {
+    /// 更新会话的[`SessionMeta`], 返回是否需要写回store
+    fn touch(
+        session: &mut async_session::Session,
+        client: ClientInfo,
+    ) -> bool {
+        let meta = match session.get::<SessionMeta>(SESSION_META_KEY) {
+            Some(meta) => meta,
+            None => return false,
+        };
+        let now = Utc::now();
+        if now - meta.last_seen < Duration::seconds(TOUCH_INTERVAL_SECS)
+        {
+            return false;
+        }
+
+        session
+            .insert(
+                SESSION_META_KEY,
+                SessionMeta {
+                    last_seen: now,
+                    ip: client
+                        .ip
+                        .map(|ip| ip.to_string().into())
+                        .or(meta.ip),
+                    user_agent: client.user_agent.or(meta.user_agent),
+                    ..meta
+                },
+            )
+            .is_ok()
     }
 }
 
@@ -0,0 +1,311 @@
+use std::fs::{metadata, read, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use anyhow::Context;
+use arc_swap::ArcSwap;
+use async_session::{Session, SessionStore};
+use chacha20poly1305::aead::{Aead, NewAead};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand_core::{OsRng, RngCore};
+
+use timer::{Follow, Task};
+
+pub const SESSION_KEY_FILE_NAME: &str = "session.key";
+pub const OLD_SESSION_KEY_FILE_NAME: &str = "session.key.old";
+
+const COOKIE_VERSION: u8 = 1;
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+/// 不在服务端保存session, 而是把session加密并认证后整个放进cookie
+///
+/// 因为没有服务端状态, 单个session无法被撤销,
+/// `clear_store`会直接更换密钥(不保留旧密钥)来使全部session失效
+#[derive(Clone)]
+pub struct CookieStore {
+    keys: Arc<ArcSwap<Keys>>,
+    data_path: PathBuf,
+}
+
+struct Keys {
+    current: XChaCha20Poly1305,
+    /// 轮换后在宽限期内仍然接受的旧密钥
+    old: Option<XChaCha20Poly1305>,
+    modified: Option<SystemTime>,
+}
+
+impl CookieStore {
+    pub fn new(data_path: &Path) -> anyhow::Result<Self> {
+        let store = CookieStore {
+            keys: Arc::new(ArcSwap::from_pointee(Keys::load(data_path)?)),
+            data_path: data_path.to_path_buf(),
+        };
+        store.regularly_reload_keys();
+        Ok(store)
+    }
+
+    pub async fn list_sessions(&self) -> anyhow::Result<Vec<Session>> {
+        Ok(Vec::new())
+    }
+
+    pub async fn find_session(
+        &self,
+        _session_id: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        Ok(None)
+    }
+
+    fn encrypt(&self, session: &Session) -> anyhow::Result<String> {
+        let mut nonce = [0; NONCE_LEN];
+        OsRng.fill_bytes(&mut nonce);
+        let ciphertext = self
+            .keys
+            .load()
+            .current
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                &*bincode::serialize(session)?,
+            )
+            .map_err(|_| anyhow::anyhow!("failed to encrypt session"))?;
+
+        let mut data =
+            Vec::with_capacity(1 + NONCE_LEN + ciphertext.len());
+        data.push(COOKIE_VERSION);
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&ciphertext);
+        Ok(base64::encode_config(data, base64::URL_SAFE_NO_PAD))
+    }
+
+    /// 无法解密(密钥已更换或者被篡改)的cookie视为没有session
+    fn decrypt(&self, cookie_value: &str) -> Option<Session> {
+        let data =
+            base64::decode_config(cookie_value, base64::URL_SAFE_NO_PAD)
+                .ok()?;
+        if data.len() <= 1 + NONCE_LEN || data[0] != COOKIE_VERSION {
+            return None;
+        }
+        let (nonce, ciphertext) = data[1..].split_at(NONCE_LEN);
+        let nonce = XNonce::from_slice(nonce);
+
+        let keys = self.keys.load();
+        let plaintext = keys
+            .current
+            .decrypt(nonce, ciphertext)
+            .ok()
+            .or_else(|| {
+                keys.old
+                    .as_ref()
+                    .and_then(|old| old.decrypt(nonce, ciphertext).ok())
+            })?;
+        bincode::deserialize::<Session>(&plaintext).ok()
+    }
+
+    /// 定期检查密钥文件, 使cli轮换的密钥不需要重启就能生效
+    fn regularly_reload_keys(&self) {
+        let keys = Arc::clone(&self.keys);
+        let data_path = self.data_path.clone();
+        global_resource::TIME_WHEEL.add_task(Task::interval(
+            move || {
+                let keys = Arc::clone(&keys);
+                let data_path = data_path.clone();
+                Box::pin(async move {
+                    let modified = Keys::modified(&data_path);
+                    let grace_over = keys.load().old.is_some()
+                        && Keys::old_key_expired(&data_path);
+                    if modified != keys.load().modified || grace_over {
+                        match Keys::load(&data_path) {
+                            Ok(new) => {
+                                log::info!("session keys reloaded");
+                                keys.store(Arc::new(new))
+                            }
+                            Err(err) => log::error!(
+                                "failed to reload session keys: {:?}",
+                                err
+                            ),
+                        }
+                    }
+                    Follow::Done
+                })
+            },
+            std::time::Duration::from_secs(60),
+        ));
+    }
+}
+
+impl Keys {
+    fn load(data_path: &Path) -> anyhow::Result<Self> {
+        let key_path = data_path.join(SESSION_KEY_FILE_NAME);
+        if !key_path.exists() {
+            write_key(&key_path, &generate_key())?;
+        }
+
+        let old_path = data_path.join(OLD_SESSION_KEY_FILE_NAME);
+        let old = if old_path.exists()
+            && !Keys::old_key_expired(data_path)
+        {
+            Some(Keys::cipher(&old_path)?)
+        } else {
+            None
+        };
+
+        Ok(Keys {
+            current: Keys::cipher(&key_path)?,
+            old,
+            modified: Keys::modified(data_path),
+        })
+    }
+
+    fn cipher(path: &Path) -> anyhow::Result<XChaCha20Poly1305> {
+        let key = read(path)
+            .with_context(|| format!("read {}", path.display()))?;
+        anyhow::ensure!(
+            key.len() == KEY_LEN,
+            "invalid session key: {}",
+            path.display()
+        );
+        Ok(XChaCha20Poly1305::new(Key::from_slice(&key)))
+    }
+
+    fn modified(data_path: &Path) -> Option<SystemTime> {
+        metadata(data_path.join(SESSION_KEY_FILE_NAME))
+            .and_then(|meta| meta.modified())
+            .ok()
+    }
+
+    /// 旧密钥文件的修改时间就是轮换的时间
+    fn old_key_expired(data_path: &Path) -> bool {
+        let grace = *config::get_config_temp()
+            .http()
+            .cookie_key_grace()
+            .duration();
+        metadata(data_path.join(OLD_SESSION_KEY_FILE_NAME))
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|rotated| rotated.elapsed().ok())
+            .map(|elapsed| elapsed > grace)
+            .unwrap_or(true)
+    }
+}
+
+/// 生成新的密钥, 当前密钥会作为旧密钥在宽限期内继续有效.
+/// `keep_old`为false时旧密钥会被直接丢弃
+pub fn rotate_key(data_path: &Path, keep_old: bool) -> anyhow::Result<()> {
+    let key_path = data_path.join(SESSION_KEY_FILE_NAME);
+    let old_path = data_path.join(OLD_SESSION_KEY_FILE_NAME);
+    if keep_old && key_path.exists() {
+        // 重新写入而不是重命名, 让修改时间记录轮换的时间
+        write_key(&old_path, &read(&key_path)?)?;
+    } else if old_path.exists() {
+        std::fs::remove_file(&old_path)?;
+    }
+    write_key(&key_path, &generate_key())
+}
+
+fn generate_key() -> [u8; KEY_LEN] {
+    let mut key = [0; KEY_LEN];
+    OsRng.fill_bytes(&mut key);
+    key
+}
+
+fn write_key(path: &Path, key: &[u8]) -> anyhow::Result<()> {
+    let mut options = OpenOptions::new();
+    options.create(true).write(true).truncate(true);
+    #[cfg(target_family = "unix")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("write {}", path.display()))?;
+    file.write_all(key)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+impl std::fmt::Debug for CookieStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieStore")
+            .field("data_path", &self.data_path)
+            .finish()
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for CookieStore {
+    async fn load_session(
+        &self,
+        cookie_value: String,
+    ) -> async_session::Result<Option<Session>> {
+        Ok(self.decrypt(&cookie_value).and_then(Session::validate))
+    }
+
+    async fn store_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result<Option<String>> {
+        self.encrypt(&session).map(Some)
+    }
+
+    async fn destroy_session(
+        &self,
+        _session: Session,
+    ) -> async_session::Result {
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        rotate_key(&self.data_path, false)?;
+        self.keys.store(Arc::new(Keys::load(&self.data_path)?));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use async_session::{Session, SessionStore};
+
+    use super::{rotate_key, CookieStore, Keys};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn cookie_store_test() {
+        config::init(vec![]).unwrap();
+        let data_path = std::env::temp_dir()
+            .join(format!("maop-cookie-store-{}", std::process::id()));
+        std::fs::create_dir_all(&data_path).unwrap();
+        let store = CookieStore::new(&data_path).unwrap();
+
+        let mut session = Session::new();
+        session.insert("key", "value").unwrap();
+        let cookie = store
+            .store_session(session.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        let loaded =
+            store.load_session(cookie.clone()).await.unwrap().unwrap();
+        assert_eq!(loaded.id(), session.id());
+        assert_eq!(loaded.get::<String>("key").unwrap(), "value");
+
+        let mut tampered = cookie.into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert!(store.load_session(tampered).await.unwrap().is_none());
+
+        // 旧密钥在宽限期内依然有效
+        let cookie = store.store_session(session).await.unwrap().unwrap();
+        rotate_key(&data_path, true).unwrap();
+        store.keys.store(Arc::new(Keys::load(&data_path).unwrap()));
+        assert!(store.load_session(cookie.clone()).await.unwrap().is_some());
+
+        store.clear_store().await.unwrap();
+        assert!(store.load_session(cookie).await.unwrap().is_none());
+
+        std::fs::remove_dir_all(&data_path).ok();
+    }
+}
@@ -4,6 +4,7 @@ use std::fs::{
     create_dir, create_dir_all, read_dir, remove_dir_all, remove_file,
 };
 use std::hash::{Hash, Hasher};
+use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
@@ -15,11 +16,6 @@ use tokio::sync::Mutex;
 
 use timer::{Follow, Task};
 
-#[cfg(not(feature = "session_store_rocksdb"))]
-pub type SessionStore = FileStore;
-#[cfg(feature = "session_store_rocksdb")]
-pub type SessionStore = rocksdb::RocksdbStore;
-
 #[derive(Debug, Clone)]
 pub struct FileStore {
     cache: Arc<Mutex<HashMap<CompactString, Session>>>,
@@ -63,17 +59,46 @@ impl FileStore {
         session_id: &str,
     ) -> anyhow::Result<Option<Session>> {
         let path = self.path.join(FileStore::id_hash(session_id));
-        Ok(if path.exists() {
-            let mut file: File =
-                OpenOptions::new().read(true).open(path).await?;
-            let mut data = Vec::with_capacity(200);
-            file.read_to_end(&mut data).await?;
-            Some(bincode::deserialize(&*data)?)
-        } else {
-            None
+        Ok(match read_file(&path).await? {
+            Some(data) => Some(bincode::deserialize(&*data)?),
+            None => None,
         })
     }
 
+    /// 列出所有未过期的session.
+    /// 文件可能刚好被清理过期session或者撤销删掉, 损坏的文件也只是跳过
+    pub async fn list_sessions(&self) -> anyhow::Result<Vec<Session>> {
+        let mut sessions = Vec::new();
+        let mut dir = tokio::fs::read_dir(&self.path).await?;
+        while let Some(dir_entry) = dir.next_entry().await? {
+            let path = dir_entry.path();
+            let data = match read_file(&path).await? {
+                Some(data) => data,
+                None => continue,
+            };
+
+            match bincode::deserialize::<Session>(&*data) {
+                Ok(session) if !session.is_expired() => {
+                    sessions.push(session)
+                }
+                Ok(_) => {}
+                Err(err) => log::warn!(
+                    "skip invalid session file {}: {}",
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        Ok(sessions)
+    }
+
+    pub async fn find_session(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        Ok(self.load(session_id).await?.and_then(Session::validate))
+    }
+
     fn regularly_check_expired(
         path: &Path,
         cache: Arc<Mutex<HashMap<CompactString, Session>>>,
@@ -122,6 +147,15 @@ impl FileStore {
     }
 }
 
+/// 文件不存在时返回`None`
+async fn read_file(path: &Path) -> std::io::Result<Option<Vec<u8>>> {
+    match tokio::fs::read(path).await {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
 #[async_trait::async_trait]
 impl AsyncSessionStore for FileStore {
     async fn load_session(
@@ -130,6 +164,15 @@ impl AsyncSessionStore for FileStore {
     ) -> async_session::Result<Option<Session>> {
         let id = Session::id_from_cookie_value(&cookie_value)?;
         let mut cache = self.cache.lock().await;
+        // 命令行撤销session时只删除文件, 缓存也要跟着失效
+        let path = self.path.join(FileStore::id_hash(&id));
+        if let Err(err) = tokio::fs::metadata(&path).await {
+            if err.kind() != ErrorKind::NotFound {
+                return Err(err.into());
+            }
+            cache.remove(&*id);
+            return Ok(None);
+        }
         Ok(if let Some(session) = cache.get(&*id) {
             Some(session.clone())
         } else {
@@ -173,112 +216,3 @@ impl AsyncSessionStore for FileStore {
         Ok(())
     }
 }
-
-#[cfg(feature = "session_store_rocksdb")]
-mod rocksdb {
-    use std::path::Path;
-    use std::sync::Arc;
-
-    use async_session::{Session, SessionStore};
-    use rocksdb::{IteratorMode, Options, DB};
-
-    use timer::{Follow, Task};
-
-    #[derive(Debug, Clone)]
-    pub struct RocksdbStore {
-        inner: Arc<DB>,
-    }
-
-    impl RocksdbStore {
-        pub async fn new<P>(path: P) -> Result<Self, rocksdb::Error>
-        where
-            P: AsRef<Path>,
-        {
-            let inner = Arc::new(DB::open(
-                &{
-                    let mut opt = Options::default();
-                    opt.create_if_missing(true);
-                    opt.set_keep_log_file_num(100);
-                    opt.set_max_log_file_size(1024 ^ 2);
-                    opt.set_recycle_log_file_num(100);
-                    opt.create_missing_column_families(true);
-                    opt
-                },
-                path,
-            )?);
-            RocksdbStore::regularly_check_expired(
-                path.as_ref(),
-                &inner,
-            );
-            Ok(RocksdbStore { inner })
-        }
-
-        fn regularly_check_expired(path: &Path, db: &Arc<DB>) {
-            let path = path.to_path_buf();
-            global_resource::TIME_WHEEL.add_task(Task::interval(
-                move || {
-                    log::debug!("checking for expired session");
-
-                    let path = path.clone();
-                    let db = Arc::clone(&db);
-                    Box::pin(async {
-                        if let Result::<(), anyhow::Error>::Err(err) = try {
-                            for (key, val) in db.full_iterator(IteratorMode::Start)
-                            {
-                                let session = bincode::deserialize::<Session>(&val)?;
-                                if session.is_expired() {
-                                    db.delete(key)?;
-                                    log::info!("Deleted the expired session: {}", session.id());
-                                }
-                            }
-                        } {
-                            log::error!("regularly_check_expired: {:?}", err);
-                        }
-                        Follow::Done
-                    })
-                },
-                *config::get_config_temp().http().overdue_check_interval().duration(),
-            ));
-        }
-    }
-
-    #[async_trait::async_trait]
-    impl SessionStore for RocksdbStore {
-        async fn load_session(
-            &self,
-            cookie_value: String,
-        ) -> async_session::Result<Option<Session>> {
-            let id = Session::id_from_cookie_value(&cookie_value)?;
-            Ok(match self.inner.get(id)? {
-                None => None,
-                Some(data) => Some(bincode::deserialize(&data)?),
-            })
-        }
-
-        async fn store_session(
-            &self,
-            session: Session,
-        ) -> async_session::Result<Option<String>> {
-            self.inner
-                .put(session.id(), bincode::serialize(&session)?)?;
-            Ok(session.into_cookie_value())
-        }
-
-        async fn destroy_session(
-            &self,
-            session: Session,
-        ) -> async_session::Result {
-            self.inner.delete(session.id()).map_err(Into::into)
-        }
-
-        async fn clear_store(&self) -> async_session::Result {
-            for (key, _) in
-                self.inner.full_iterator(IteratorMode::Start)
-            {
-                self.inner.delete(key)?
-            }
-
-            Ok(())
-        }
-    }
-}
@@ -0,0 +1,193 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use async_session::{Session, SessionStore as AsyncSessionStore};
+use chrono::{DateTime, Utc};
+use compact_str::CompactString;
+use sea_orm::DatabaseConnection;
+
+use config::SessionStoreType;
+
+pub use self::cookie::{rotate_key, CookieStore};
+pub use self::file::FileStore;
+#[cfg(feature = "session_store_redis")]
+pub use self::redis::RedisStore;
+#[cfg(feature = "session_store_rocksdb")]
+pub use self::rocksdb::RocksdbStore;
+pub use self::sqlite::SqliteStore;
+
+mod cookie;
+mod file;
+#[cfg(feature = "session_store_redis")]
+mod redis;
+#[cfg(feature = "session_store_rocksdb")]
+mod rocksdb;
+mod sqlite;
+
+/// session数据中保存[`SessionMeta`]的key
+pub const SESSION_META_KEY: &str = "meta";
+
+/// 登录会话的附加信息, 随session数据一起保存在store中
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SessionMeta {
+    pub create_time: DateTime<Utc>,
+    pub last_seen: DateTime<Utc>,
+    pub ip: Option<CompactString>,
+    pub user_agent: Option<CompactString>,
+}
+
+impl SessionMeta {
+    pub fn new(
+        ip: Option<CompactString>,
+        user_agent: Option<CompactString>,
+    ) -> Self {
+        let now = Utc::now();
+        SessionMeta {
+            create_time: now,
+            last_seen: now,
+            ip,
+            user_agent,
+        }
+    }
+}
+
+/// 供管理员查看的会话信息
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub expiry: Option<DateTime<Utc>>,
+    pub meta: Option<SessionMeta>,
+}
+
+impl From<&Session> for SessionInfo {
+    fn from(session: &Session) -> Self {
+        SessionInfo {
+            id: session.id().to_owned(),
+            expiry: session.expiry().cloned(),
+            meta: session.get(SESSION_META_KEY),
+        }
+    }
+}
+
+/// 运行时根据`http.session_store`选择的session store
+#[derive(Debug, Clone)]
+pub enum SessionStore {
+    File(FileStore),
+    Sqlite(SqliteStore),
+    Cookie(CookieStore),
+    #[cfg(feature = "session_store_rocksdb")]
+    Rocksdb(RocksdbStore),
+    #[cfg(feature = "session_store_redis")]
+    Redis(RedisStore),
+}
+
+macro dispatch($self:expr, $store:ident => $body:expr) {
+    match $self {
+        SessionStore::File($store) => $body,
+        SessionStore::Sqlite($store) => $body,
+        SessionStore::Cookie($store) => $body,
+        #[cfg(feature = "session_store_rocksdb")]
+        SessionStore::Rocksdb($store) => $body,
+        #[cfg(feature = "session_store_redis")]
+        SessionStore::Redis($store) => $body,
+    }
+}
+
+impl SessionStore {
+    pub async fn open(
+        data_path: &Path,
+        db: &Arc<DatabaseConnection>,
+    ) -> anyhow::Result<Self> {
+        let config = config::get_config_full();
+        Ok(match config.http().session_store() {
+            SessionStoreType::File => SessionStore::File(
+                FileStore::new(data_path.join("sessions")).await?,
+            ),
+            SessionStoreType::Sqlite => {
+                SessionStore::Sqlite(SqliteStore::new(Arc::clone(db)))
+            }
+            SessionStoreType::Cookie => {
+                SessionStore::Cookie(CookieStore::new(data_path)?)
+            }
+            #[cfg(feature = "session_store_rocksdb")]
+            SessionStoreType::Rocksdb => SessionStore::Rocksdb(
+                RocksdbStore::new(data_path.join("sessions")).await?,
+            ),
+            #[cfg(not(feature = "session_store_rocksdb"))]
+            SessionStoreType::Rocksdb => anyhow::bail!(
+                "the `session_store_rocksdb` feature is not enabled"
+            ),
+            #[cfg(feature = "session_store_redis")]
+            SessionStoreType::Redis => {
+                let redis = config.http().redis();
+                SessionStore::Redis(
+                    RedisStore::new(
+                        redis.addr().clone(),
+                        *redis.db(),
+                        redis.password().clone(),
+                        redis.key_prefix().clone(),
+                    )
+                    .await?,
+                )
+            }
+            #[cfg(not(feature = "session_store_redis"))]
+            SessionStoreType::Redis => anyhow::bail!(
+                "the `session_store_redis` feature is not enabled"
+            ),
+        })
+    }
+
+    /// session是否完全保存在cookie中, 服务端无法修改或撤销
+    #[inline]
+    pub fn is_stateless(&self) -> bool {
+        matches!(self, SessionStore::Cookie(_))
+    }
+
+    /// 列出所有未过期的session
+    pub async fn list_sessions(&self) -> anyhow::Result<Vec<Session>> {
+        dispatch!(self, store => store.list_sessions().await)
+    }
+
+    pub async fn find_session(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        dispatch!(self, store => store.find_session(session_id).await)
+    }
+
+    /// 未过期的session数量, cookie模式无法统计时为`None`
+    pub async fn count(&self) -> anyhow::Result<Option<usize>> {
+        if self.is_stateless() {
+            return Ok(None);
+        }
+        Ok(Some(self.list_sessions().await?.len()))
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncSessionStore for SessionStore {
+    async fn load_session(
+        &self,
+        cookie_value: String,
+    ) -> async_session::Result<Option<Session>> {
+        dispatch!(self, store => store.load_session(cookie_value).await)
+    }
+
+    async fn store_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result<Option<String>> {
+        dispatch!(self, store => store.store_session(session).await)
+    }
+
+    async fn destroy_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result {
+        dispatch!(self, store => store.destroy_session(session).await)
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        dispatch!(self, store => store.clear_store().await)
+    }
+}
@@ -0,0 +1,533 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use async_session::{Session, SessionStore};
+use compact_str::CompactString;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+
+/// 通过RESP协议把session保存到redis兼容的服务器中,
+/// 过期由服务器的key ttl负责
+#[derive(Debug, Clone)]
+pub struct RedisStore {
+    conn: Arc<Mutex<Option<RespConnection>>>,
+    addr: CompactString,
+    db: u32,
+    password: Option<CompactString>,
+    key_prefix: CompactString,
+}
+
+impl RedisStore {
+    pub async fn new(
+        addr: CompactString,
+        db: u32,
+        password: Option<CompactString>,
+        key_prefix: CompactString,
+    ) -> anyhow::Result<Self> {
+        let store = RedisStore {
+            conn: Arc::new(Mutex::new(None)),
+            addr,
+            db,
+            password,
+            key_prefix,
+        };
+        // 启动时就检查服务器是否可用
+        store.command(&[b"PING"]).await?;
+        Ok(store)
+    }
+
+    pub async fn list_sessions(&self) -> anyhow::Result<Vec<Session>> {
+        let mut sessions = Vec::new();
+        for key in self.scan_keys().await? {
+            if let Some(session) = self.get(&key).await? {
+                sessions.push(session);
+            }
+        }
+        Ok(sessions)
+    }
+
+    pub async fn find_session(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        self.get(self.key(session_id).as_bytes()).await
+    }
+
+    #[inline]
+    fn key(&self, session_id: &str) -> String {
+        format!("{}{}", self.key_prefix, session_id)
+    }
+
+    async fn get(&self, key: &[u8]) -> anyhow::Result<Option<Session>> {
+        Ok(match self.command(&[b"GET", key]).await? {
+            Reply::Bulk(Some(data)) => {
+                bincode::deserialize::<Session>(&data)?.validate()
+            }
+            Reply::Bulk(None) => None,
+            other => anyhow::bail!("unexpected reply to GET: {:?}", other),
+        })
+    }
+
+    async fn scan_keys(&self) -> anyhow::Result<Vec<Vec<u8>>> {
+        let pattern = format!("{}*", self.key_prefix);
+        let mut cursor = b"0".to_vec();
+        let mut keys = Vec::new();
+        loop {
+            let reply = self
+                .command(&[
+                    b"SCAN",
+                    &cursor,
+                    b"MATCH",
+                    pattern.as_bytes(),
+                    b"COUNT",
+                    b"100",
+                ])
+                .await?;
+            match reply {
+                Reply::Array(Some(mut items)) if items.len() == 2 => {
+                    if let Reply::Array(Some(batch)) = items.pop().unwrap()
+                    {
+                        keys.extend(batch.into_iter().filter_map(
+                            |item| match item {
+                                Reply::Bulk(key) => key,
+                                _ => None,
+                            },
+                        ));
+                    }
+                    match items.pop().unwrap() {
+                        Reply::Bulk(Some(next)) => cursor = next,
+                        other => anyhow::bail!(
+                            "unexpected SCAN cursor: {:?}",
+                            other
+                        ),
+                    }
+                }
+                other => {
+                    anyhow::bail!("unexpected reply to SCAN: {:?}", other)
+                }
+            }
+            if cursor == b"0" {
+                break Ok(keys);
+            }
+        }
+    }
+
+    /// 执行命令, 连接断开时重连一次.
+    /// 执行期间连接不在`conn`中, 请求被取消时连接里可能还有没读取的回复,
+    /// 直接丢弃, 下一个请求重新连接
+    async fn command(&self, args: &[&[u8]]) -> anyhow::Result<Reply> {
+        let mut guard = self.conn.lock().await;
+        for retry in [false, true] {
+            let mut conn = match guard.take() {
+                Some(conn) => conn,
+                None => self.connect().await?,
+            };
+            match conn.command(args).await {
+                Ok(reply) => {
+                    *guard = Some(conn);
+                    if let Reply::Error(err) = reply {
+                        anyhow::bail!("redis: {}", err);
+                    }
+                    return Ok(reply);
+                }
+                Err(err) => {
+                    if retry {
+                        return Err(err).context("redis command");
+                    }
+                    log::warn!("redis connection lost: {}", err);
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    async fn connect(&self) -> anyhow::Result<RespConnection> {
+        let mut conn = RespConnection::new(
+            TcpStream::connect(self.addr.as_str())
+                .await
+                .with_context(|| format!("connect to redis {}", self.addr))?,
+        );
+        if let Some(password) = &self.password {
+            conn.expect_ok(&[b"AUTH", password.as_bytes()]).await?;
+        }
+        if self.db != 0 {
+            conn.expect_ok(&[b"SELECT", self.db.to_string().as_bytes()])
+                .await?;
+        }
+        Ok(conn)
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RedisStore {
+    async fn load_session(
+        &self,
+        cookie_value: String,
+    ) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        self.find_session(&id).await
+    }
+
+    async fn store_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result<Option<String>> {
+        let key = self.key(session.id());
+        let data = bincode::serialize(&session)?;
+        // 过期后`expires_in`为`None`, 要和没有过期时间区分开
+        match (session.expiry(), session.expires_in()) {
+            (None, _) => {
+                self.command(&[b"SET", key.as_bytes(), &data]).await?;
+            }
+            (Some(_), Some(ttl)) => {
+                // 不足1ms时`PX 0`会被拒绝
+                let ttl = ttl.as_millis().max(1);
+                self.command(&[
+                    b"SET",
+                    key.as_bytes(),
+                    &data,
+                    b"PX",
+                    ttl.to_string().as_bytes(),
+                ])
+                .await?;
+            }
+            // 已经过期, 没必要再保存
+            (Some(_), None) => {
+                self.command(&[b"DEL", key.as_bytes()]).await?;
+            }
+        }
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result {
+        self.command(&[b"DEL", self.key(session.id()).as_bytes()])
+            .await?;
+        Ok(())
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        for key in self.scan_keys().await? {
+            self.command(&[b"DEL", &key]).await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub(crate) enum Reply {
+    Simple(String),
+    Error(String),
+    Integer(i64),
+    Bulk(Option<Vec<u8>>),
+    Array(Option<Vec<Reply>>),
+}
+
+#[derive(Debug)]
+pub(crate) struct RespConnection {
+    stream: BufStream<TcpStream>,
+}
+
+impl RespConnection {
+    pub(crate) fn new(stream: TcpStream) -> Self {
+        RespConnection {
+            stream: BufStream::new(stream),
+        }
+    }
+
+    pub(crate) async fn command(
+        &mut self,
+        args: &[&[u8]],
+    ) -> anyhow::Result<Reply> {
+        self.write_command(args).await?;
+        self.read_reply().await
+    }
+
+    async fn expect_ok(&mut self, args: &[&[u8]]) -> anyhow::Result<()> {
+        match self.command(args).await? {
+            Reply::Simple(_) => Ok(()),
+            other => anyhow::bail!(
+                "redis {}: {:?}",
+                String::from_utf8_lossy(args[0]),
+                other
+            ),
+        }
+    }
+
+    pub(crate) async fn write_command(
+        &mut self,
+        args: &[&[u8]],
+    ) -> std::io::Result<()> {
+        let mut buf = Vec::with_capacity(64);
+        buf.extend_from_slice(format!("*{}\r\n", args.len()).as_bytes());
+        for arg in args {
+            buf.extend_from_slice(format!("${}\r\n", arg.len()).as_bytes());
+            buf.extend_from_slice(arg);
+            buf.extend_from_slice(b"\r\n");
+        }
+        self.write_raw(&buf).await
+    }
+
+    pub(crate) async fn write_raw(
+        &mut self,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        self.stream.write_all(data).await?;
+        self.stream.flush().await
+    }
+
+    pub(crate) fn read_reply(
+        &mut self,
+    ) -> BoxFuture<'_, anyhow::Result<Reply>> {
+        async move {
+            let line = self.read_line().await?;
+            // 第一个字节不是ascii时不能按字符切分
+            let (ty, content) = match line.as_bytes().first() {
+                Some(ty) if ty.is_ascii() => (*ty, &line[1..]),
+                _ => anyhow::bail!("invalid RESP type: {:?}", line),
+            };
+            Ok(match ty {
+                b'+' => Reply::Simple(content.to_owned()),
+                b'-' => Reply::Error(content.to_owned()),
+                b':' => Reply::Integer(content.parse()?),
+                b'$' => {
+                    let len: i64 = content.parse()?;
+                    if len < 0 {
+                        Reply::Bulk(None)
+                    } else {
+                        let mut data = vec![0; len as usize + 2];
+                        self.stream.read_exact(&mut data).await?;
+                        data.truncate(len as usize);
+                        Reply::Bulk(Some(data))
+                    }
+                }
+                b'*' => {
+                    let len: i64 = content.parse()?;
+                    if len < 0 {
+                        Reply::Array(None)
+                    } else {
+                        let mut items = Vec::with_capacity(len as usize);
+                        for _ in 0..len {
+                            items.push(self.read_reply().await?);
+                        }
+                        Reply::Array(Some(items))
+                    }
+                }
+                _ => anyhow::bail!("invalid RESP type: {:?}", line),
+            })
+        }
+        .boxed()
+    }
+
+    async fn read_line(&mut self) -> anyhow::Result<String> {
+        let mut line = String::new();
+        if self.stream.read_line(&mut line).await? == 0 {
+            anyhow::bail!("connection closed");
+        }
+        let line = line.trim_end_matches("\r\n");
+        anyhow::ensure!(!line.is_empty(), "empty RESP line");
+        Ok(line.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use async_session::{Session, SessionStore};
+    use tokio::net::TcpListener;
+    use tokio::sync::Mutex;
+
+    use super::{RedisStore, Reply, RespConnection};
+
+    type Db = Arc<Mutex<HashMap<Vec<u8>, (Vec<u8>, Option<Instant>)>>>;
+
+    /// 只实现了store用到的命令的RESP服务器
+    async fn stand_in_server() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let db = Db::default();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let db = Arc::clone(&db);
+                tokio::spawn(async move {
+                    let mut conn = RespConnection::new(stream);
+                    while let Ok(Reply::Array(Some(args))) =
+                        conn.read_reply().await
+                    {
+                        let args = args
+                            .into_iter()
+                            .map(|arg| match arg {
+                                Reply::Bulk(Some(arg)) => arg,
+                                _ => Vec::new(),
+                            })
+                            .collect::<Vec<_>>();
+                        let reply = execute(&db, args).await;
+                        // 客户端取消请求后可能已经断开
+                        if conn.write_raw(&reply).await.is_err() {
+                            break;
+                        }
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    async fn execute(db: &Db, args: Vec<Vec<u8>>) -> Vec<u8> {
+        fn bulk(data: &[u8]) -> Vec<u8> {
+            let mut buf = format!("${}\r\n", data.len()).into_bytes();
+            buf.extend_from_slice(data);
+            buf.extend_from_slice(b"\r\n");
+            buf
+        }
+
+        // 和redis的`DEBUG SLEEP`一样, 延迟回复
+        if args[0] == b"DEBUG" {
+            let secs = String::from_utf8_lossy(&args[2]).parse().unwrap();
+            tokio::time::sleep(Duration::from_secs_f64(secs)).await;
+            return b"+OK\r\n".to_vec();
+        }
+
+        let mut db = db.lock().await;
+        db.retain(|_, (_, expiry)| {
+            expiry.map(|e| e > Instant::now()).unwrap_or(true)
+        });
+        match args[0].as_slice() {
+            b"PING" => b"+PONG\r\n".to_vec(),
+            b"SET" => {
+                let expiry = (args.len() == 5).then(|| {
+                    let ms = String::from_utf8_lossy(&args[4])
+                        .parse()
+                        .unwrap();
+                    Instant::now() + Duration::from_millis(ms)
+                });
+                db.insert(args[1].clone(), (args[2].clone(), expiry));
+                b"+OK\r\n".to_vec()
+            }
+            b"GET" => match db.get(&args[1]) {
+                Some((data, _)) => bulk(data),
+                None => b"$-1\r\n".to_vec(),
+            },
+            b"DEL" => format!(
+                ":{}\r\n",
+                db.remove(&args[1]).is_some() as u8
+            )
+            .into_bytes(),
+            b"SCAN" => {
+                let prefix = args[3].strip_suffix(b"*").unwrap();
+                let keys = db
+                    .keys()
+                    .filter(|key| key.starts_with(prefix))
+                    .collect::<Vec<_>>();
+                let mut buf = b"*2\r\n".to_vec();
+                buf.extend(bulk(b"0"));
+                buf.extend(format!("*{}\r\n", keys.len()).as_bytes());
+                keys.into_iter().for_each(|key| buf.extend(bulk(key)));
+                buf
+            }
+            _ => b"-ERR unknown command\r\n".to_vec(),
+        }
+    }
+
+    async fn store_test(addr: String) {
+        let store = RedisStore::new(
+            addr.into(),
+            0,
+            None,
+            "maop:test:session:".into(),
+        )
+        .await
+        .unwrap();
+        store.clear_store().await.unwrap();
+
+        let mut session = Session::new();
+        session.insert("key", "value").unwrap();
+        let cookie = store
+            .store_session(session.clone())
+            .await
+            .unwrap()
+            .unwrap();
+
+        let loaded = store.load_session(cookie).await.unwrap().unwrap();
+        assert_eq!(loaded.id(), session.id());
+        assert_eq!(loaded.get::<String>("key").unwrap(), "value");
+        assert_eq!(store.list_sessions().await.unwrap().len(), 1);
+
+        let mut short = Session::new();
+        short.expire_in(Duration::from_millis(100));
+        let short_id = short.id().to_owned();
+        store.store_session(short).await.unwrap();
+        assert!(store.find_session(&short_id).await.unwrap().is_some());
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert!(store.find_session(&short_id).await.unwrap().is_none());
+
+        // 已经过期的session不会被保存成永不过期
+        let mut expired = Session::new();
+        expired.set_expiry(chrono::Utc::now() - chrono::Duration::seconds(1));
+        let expired_id = expired.id().to_owned();
+        store.store_session(expired).await.unwrap();
+        assert!(store.find_session(&expired_id).await.unwrap().is_none());
+
+        store.destroy_session(loaded).await.unwrap();
+        assert!(store
+            .find_session(session.id())
+            .await
+            .unwrap()
+            .is_none());
+
+        store.store_session(Session::new()).await.unwrap();
+        store.clear_store().await.unwrap();
+        assert!(store.list_sessions().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn redis_store_test() {
+        store_test(stand_in_server().await).await;
+    }
+
+    /// 取消的请求的回复不能被下一个请求读到
+    #[tokio::test]
+    async fn cancelled_command_test() {
+        let store = RedisStore::new(
+            stand_in_server().await.into(),
+            0,
+            None,
+            "maop:test:session:".into(),
+        )
+        .await
+        .unwrap();
+
+        let mut session = Session::new();
+        session.insert("key", "value").unwrap();
+        store.store_session(session.clone()).await.unwrap();
+
+        let slow = store.command(&[b"DEBUG", b"SLEEP", b"0.2"]);
+        assert!(tokio::time::timeout(Duration::from_millis(50), slow)
+            .await
+            .is_err());
+
+        assert_eq!(
+            store.command(&[b"PING"]).await.unwrap(),
+            Reply::Simple("PONG".to_owned())
+        );
+        assert!(store.find_session("other").await.unwrap().is_none());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert!(store.find_session("other").await.unwrap().is_none());
+        assert!(store.find_session(session.id()).await.unwrap().is_some());
+    }
+
+    #[ignore]
+    #[tokio::test]
+    async fn redis_server_store_test() {
+        store_test("127.0.0.1:6379".to_owned()).await;
+    }
+}
@@ -0,0 +1,130 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use async_session::{Session, SessionStore};
+use rocksdb::{IteratorMode, Options, DB};
+
+use timer::{Follow, Task};
+
+#[derive(Debug, Clone)]
+pub struct RocksdbStore {
+    inner: Arc<DB>,
+}
+
+impl RocksdbStore {
+    pub async fn new<P>(path: P) -> Result<Self, rocksdb::Error>
+    where
+        P: AsRef<Path>,
+    {
+        let inner = Arc::new(DB::open(
+            &{
+                let mut opt = Options::default();
+                opt.create_if_missing(true);
+                opt.set_keep_log_file_num(100);
+                opt.set_max_log_file_size(1024 ^ 2);
+                opt.set_recycle_log_file_num(100);
+                opt.create_missing_column_families(true);
+                opt
+            },
+            path,
+        )?);
+        RocksdbStore::regularly_check_expired(
+            path.as_ref(),
+            &inner,
+        );
+        Ok(RocksdbStore { inner })
+    }
+
+    fn regularly_check_expired(path: &Path, db: &Arc<DB>) {
+        let path = path.to_path_buf();
+        global_resource::TIME_WHEEL.add_task(Task::interval(
+            move || {
+                log::debug!("checking for expired session");
+
+                let path = path.clone();
+                let db = Arc::clone(&db);
+                Box::pin(async {
+                    if let Result::<(), anyhow::Error>::Err(err) = try {
+                        for (key, val) in db.full_iterator(IteratorMode::Start)
+                        {
+                            let session = bincode::deserialize::<Session>(&val)?;
+                            if session.is_expired() {
+                                db.delete(key)?;
+                                log::info!("Deleted the expired session: {}", session.id());
+                            }
+                        }
+                    } {
+                        log::error!("regularly_check_expired: {:?}", err);
+                    }
+                    Follow::Done
+                })
+            },
+            *config::get_config_temp().http().overdue_check_interval().duration(),
+        ));
+    }
+
+    pub async fn list_sessions(
+        &self,
+    ) -> anyhow::Result<Vec<Session>> {
+        let mut sessions = Vec::new();
+        for (_, val) in self.inner.full_iterator(IteratorMode::Start)
+        {
+            let session = bincode::deserialize::<Session>(&val)?;
+            if !session.is_expired() {
+                sessions.push(session);
+            }
+        }
+        Ok(sessions)
+    }
+
+    pub async fn find_session(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        Ok(match self.inner.get(session_id)? {
+            None => None,
+            Some(data) => bincode::deserialize::<Session>(&data)?
+                .validate(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for RocksdbStore {
+    async fn load_session(
+        &self,
+        cookie_value: String,
+    ) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        Ok(match self.inner.get(id)? {
+            None => None,
+            Some(data) => Some(bincode::deserialize(&data)?),
+        })
+    }
+
+    async fn store_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result<Option<String>> {
+        self.inner
+            .put(session.id(), bincode::serialize(&session)?)?;
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result {
+        self.inner.delete(session.id()).map_err(Into::into)
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        for (key, _) in
+            self.inner.full_iterator(IteratorMode::Start)
+        {
+            self.inner.delete(key)?
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::sync::Arc;
+
+use async_session::{Session, SessionStore};
+use chrono::Utc;
+use sea_orm::DatabaseConnection;
+
+use database::models::session::{SessionModel, SessionRecord};
+use timer::{Follow, Task};
+
+/// 把session保存在`main.db`的`sessions`表中
+#[derive(Debug, Clone)]
+pub struct SqliteStore {
+    db: Arc<DatabaseConnection>,
+}
+
+impl SqliteStore {
+    pub fn new(db: Arc<DatabaseConnection>) -> Self {
+        SqliteStore::regularly_check_expired(&db);
+        SqliteStore { db }
+    }
+
+    pub async fn list_sessions(&self) -> anyhow::Result<Vec<Session>> {
+        let mut sessions = Vec::new();
+        for model in SessionRecord::find_all(&self.db).await? {
+            let session = SqliteStore::decode(&model)?;
+            if !session.is_expired() {
+                sessions.push(session);
+            }
+        }
+        Ok(sessions)
+    }
+
+    pub async fn find_session(
+        &self,
+        session_id: &str,
+    ) -> anyhow::Result<Option<Session>> {
+        Ok(match SessionRecord::find_one(&self.db, session_id).await? {
+            None => None,
+            Some(model) => SqliteStore::decode(&model)?.validate(),
+        })
+    }
+
+    #[inline]
+    fn decode(model: &SessionModel) -> anyhow::Result<Session> {
+        Ok(bincode::deserialize::<Session>(&model.data)?)
+    }
+
+    fn regularly_check_expired(db: &Arc<DatabaseConnection>) {
+        let db = Arc::clone(db);
+        global_resource::TIME_WHEEL.add_task(Task::interval(
+            move || {
+                log::debug!("checking for expired session");
+
+                let db = Arc::clone(&db);
+                Box::pin(async move {
+                    match SessionRecord::delete_expired(
+                        &db,
+                        Utc::now().naive_utc(),
+                    )
+                    .await
+                    {
+                        Ok(0) => {}
+                        Ok(count) => {
+                            log::info!("Deleted {} expired sessions", count)
+                        }
+                        Err(err) => {
+                            log::error!("regularly_check_expired: {:?}", err)
+                        }
+                    }
+                    Follow::Done
+                })
+            },
+            *config::get_config_temp().http().overdue_check_interval().duration(),
+        ));
+    }
+}
+
+#[async_trait::async_trait]
+impl SessionStore for SqliteStore {
+    async fn load_session(
+        &self,
+        cookie_value: String,
+    ) -> async_session::Result<Option<Session>> {
+        let id = Session::id_from_cookie_value(&cookie_value)?;
+        self.find_session(&id).await
+    }
+
+    async fn store_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result<Option<String>> {
+        SessionRecord::upsert(
+            &self.db,
+            session.id(),
+            bincode::serialize(&session)?,
+            session.expiry().map(|expiry| expiry.naive_utc()),
+        )
+        .await?;
+        Ok(session.into_cookie_value())
+    }
+
+    async fn destroy_session(
+        &self,
+        session: Session,
+    ) -> async_session::Result {
+        SessionRecord::delete(&self.db, session.id()).await
+    }
+
+    async fn clear_store(&self) -> async_session::Result {
+        SessionRecord::delete_all(&self.db).await
+    }
+}
@@ -0,0 +1,96 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use axum::http::Request;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::service::Service;
+use hyper::Response;
+use tower::Layer;
+
+/// 给每个响应加上一个固定的header, `value`为`None`时什么都不做
+#[derive(Clone, Debug)]
+pub struct SetHeaderLayer {
+    name: HeaderName,
+    value: Option<HeaderValue>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SetHeader<S> {
+    inner: S,
+    layer: SetHeaderLayer,
+}
+
+#[pin_project::pin_project]
+pub struct ResponseFuture<F> {
+    #[pin]
+    fut: F,
+    header: Option<(HeaderName, HeaderValue)>,
+}
+
+impl SetHeaderLayer {
+    pub fn new(name: HeaderName, value: Option<HeaderValue>) -> Self {
+        SetHeaderLayer { name, value }
+    }
+}
+
+impl<S> Layer<S> for SetHeaderLayer {
+    type Service = SetHeader<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        SetHeader {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+impl<S, ReqBody, ResBody> Service<Request<ReqBody>> for SetHeader<S>
+where
+    S: Service<Request<ReqBody>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    #[inline]
+    fn poll_ready(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<ReqBody>) -> Self::Future {
+        ResponseFuture {
+            fut: self.inner.call(req),
+            header: self
+                .layer
+                .value
+                .clone()
+                .map(|value| (self.layer.name.clone(), value)),
+        }
+    }
+}
+
+impl<F, B, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<B>, E>;
+
+    fn poll(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut response: Response<B> =
+            futures::ready!(this.fut.poll(cx))?;
+
+        if let Some((name, value)) = this.header.take() {
+            response.headers_mut().insert(name, value);
+        }
+
+        Poll::Ready(Ok(response))
+    }
+}
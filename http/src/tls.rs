@@ -0,0 +1,460 @@
+use std::borrow::Borrow;
+use std::fs::File;
+use std::io::BufReader;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use anyhow::Context as _;
+use arc_swap::ArcSwap;
+use axum::extract::connect_info::Connected;
+use futures::Stream;
+use hyper::header::{HeaderValue, HOST, LOCATION};
+use hyper::server::accept::Accept;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, StatusCode};
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+use rustls_pemfile::Item;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::server::{ClientHello, ResolvesServerCert};
+use tokio_rustls::rustls::sign::{any_supported_type, CertifiedKey};
+use tokio_rustls::rustls::{Certificate, PrivateKey, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use tokio_rustls::TlsAcceptor;
+
+use config::{MaopConfig, TlsConfig};
+use global_resource::SHUTDOWN_NOTIFY;
+
+use crate::acme;
+
+/// 持有当前证书, 证书文件变化时自动重新加载
+pub struct CertResolver {
+    key: ArcSwap<CertifiedKey>,
+    paths: Mutex<(PathBuf, PathBuf)>,
+    watcher: Mutex<Option<RecommendedWatcher>>,
+}
+
+impl CertResolver {
+    pub fn new(cert: &Path, key: &Path) -> anyhow::Result<Arc<Self>> {
+        let resolver = Arc::new(CertResolver {
+            key: ArcSwap::from_pointee(load_certified_key(cert, key)?),
+            paths: Mutex::new((cert.to_path_buf(), key.to_path_buf())),
+            watcher: Mutex::new(None),
+        });
+        resolver.watch()?;
+
+        let weak = Arc::downgrade(&resolver);
+        config::hook(Box::new(move || {
+            if let Some(resolver) = weak.upgrade() {
+                resolver.on_config_refresh();
+            }
+        }));
+
+        Ok(resolver)
+    }
+
+    pub fn reload(&self) -> anyhow::Result<()> {
+        let (cert, key) = self.paths.lock().unwrap().clone();
+        self.key.store(Arc::new(load_certified_key(&cert, &key)?));
+        log::info!("reload tls certificate {}", cert.display());
+        Ok(())
+    }
+
+    fn on_config_refresh(self: &Arc<Self>) {
+        let config = config::get_config_full();
+        let new_paths = match config
+            .http()
+            .tls()
+            .as_ref()
+            .and_then(|tls| cert_paths(tls, config.data_path()).ok())
+        {
+            Some(paths) => paths,
+            None => return,
+        };
+        {
+            let mut paths = self.paths.lock().unwrap();
+            if *paths == new_paths {
+                return;
+            }
+            *paths = new_paths;
+        }
+        if let Err(err) = self.reload().and_then(|_| self.watch()) {
+            log::error!("failed to reload tls certificate: {:?}", err);
+        }
+    }
+
+    /// 监听证书所在的目录而不是文件本身,
+    /// 因为证书通常是被替换(比如certbot更新软链接)而不是修改
+    fn watch(self: &Arc<Self>) -> anyhow::Result<()> {
+        let (cert, key) = self.paths.lock().unwrap().clone();
+        let weak: Weak<CertResolver> = Arc::downgrade(self);
+        let targets = [cert.clone(), key.clone()];
+        let mut watcher = notify::recommended_watcher(
+            move |res: notify::Result<Event>| match res {
+                Ok(event) => {
+                    let changed = (event.kind.is_create()
+                        || event.kind.is_modify())
+                        && event.paths.iter().any(|path| {
+                            targets.iter().any(|t| path.ends_with(t))
+                        });
+                    if let Some(resolver) =
+                        weak.upgrade().filter(|_| changed)
+                    {
+                        if let Err(err) = resolver.reload() {
+                            log::error!(
+                                "failed to reload tls certificate: {:?}",
+                                err
+                            );
+                        }
+                    }
+                }
+                Err(err) => log::error!("watch error: {:?}", err),
+            },
+        )?;
+        for path in [&cert, &key] {
+            let dir = path.parent().unwrap_or_else(|| Path::new("."));
+            watcher.watch(dir, RecursiveMode::NonRecursive)?;
+        }
+        *self.watcher.lock().unwrap() = Some(watcher);
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello) -> Option<Arc<CertifiedKey>> {
+        Some(self.key.load_full())
+    }
+}
+
+/// 准备https监听器共用的证书, 按需启动acme和重定向服务
+pub async fn init(
+    config: &MaopConfig,
+    https_addr: SocketAddr,
+) -> anyhow::Result<Arc<ServerConfig>> {
+    let tls = config
+        .http()
+        .tls()
+        .as_ref()
+        .context("`http.tls` is required for https")?;
+
+    if let Some(port) = tls.redirect_port() {
+        let addr = SocketAddr::new(https_addr.ip(), *port);
+        utils::task::spawn(
+            async move {
+                if let Err(err) =
+                    run_redirect_server(addr, https_addr.port()).await
+                {
+                    log::error!("redirect server error: {}", err);
+                }
+            },
+            "redirect server",
+        );
+    } else if tls.acme().is_some() {
+        anyhow::bail!(
+            "`http.tls.redirect_port` is required to answer acme challenges"
+        );
+    }
+
+    if tls.acme().is_some() {
+        acme::ensure_cert(config.data_path()).await?;
+        acme::schedule_renewal(config.data_path());
+    }
+
+    let (cert, key) = cert_paths(tls, config.data_path())?;
+    Ok(server_config(CertResolver::new(&cert, &key)?))
+}
+
+/// 配置了acme时使用acme签发的证书, 否则使用`cert`和`key`
+pub fn cert_paths(
+    tls: &TlsConfig,
+    data_path: &Path,
+) -> anyhow::Result<(PathBuf, PathBuf)> {
+    if tls.acme().is_some() {
+        return Ok(acme::cert_paths(data_path));
+    }
+    match (tls.cert(), tls.key()) {
+        (Some(cert), Some(key)) => Ok((cert.clone(), key.clone())),
+        _ => anyhow::bail!(
+            "`http.tls.cert` and `http.tls.key` are required without `http.tls.acme`"
+        ),
+    }
+}
+
+fn load_certified_key(
+    cert: &Path,
+    key: &Path,
+) -> anyhow::Result<CertifiedKey> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        File::open(cert)
+            .with_context(|| format!("open {}", cert.display()))?,
+    ))?
+    .into_iter()
+    .map(Certificate)
+    .collect::<Vec<_>>();
+    anyhow::ensure!(
+        !certs.is_empty(),
+        "no certificate found in {}",
+        cert.display()
+    );
+
+    let key = rustls_pemfile::read_all(&mut BufReader::new(
+        File::open(key)
+            .with_context(|| format!("open {}", key.display()))?,
+    ))?
+    .into_iter()
+    .find_map(|item| match item {
+        Item::RSAKey(key) | Item::PKCS8Key(key) | Item::ECKey(key) => {
+            Some(PrivateKey(key))
+        }
+        _ => None,
+    })
+    .with_context(|| format!("no private key found in {}", key.display()))?;
+
+    Ok(CertifiedKey::new(
+        certs,
+        any_supported_type(&key)
+            .map_err(|_| anyhow::anyhow!("unsupported private key"))?,
+    ))
+}
+
+pub fn server_config(resolver: Arc<CertResolver>) -> Arc<ServerConfig> {
+    let mut config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Arc::new(config)
+}
+
+/// 完成了tls握手的连接
+#[pin_project::pin_project]
+pub struct TlsConn {
+    #[pin]
+    stream: TlsStream<TcpStream>,
+    remote: SocketAddr,
+}
+
+impl Connected<&TlsConn> for SocketAddr {
+    fn connect_info(target: &TlsConn) -> Self {
+        target.remote
+    }
+}
+
+impl AsyncRead for TlsConn {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.project().stream.poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for TlsConn {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().stream.poll_write(cx, buf)
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.project().stream.poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.project().stream.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[std::io::IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().stream.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+}
+
+/// 握手超过这个时间就关闭连接, 避免空闲的连接一直占用资源
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 接受tcp连接并在单独的task中握手, 避免慢速的客户端阻塞其他连接.
+/// `listener`在不再接受连接后被drop
+pub fn incoming<L>(
+    listener: L,
+    config: Arc<ServerConfig>,
+) -> impl Accept<Conn = TlsConn, Error = std::io::Error>
+where
+    L: Borrow<TcpListener> + Send + 'static,
+{
+    let acceptor = TlsAcceptor::from(config);
+    let (tx, rx) = mpsc::channel::<std::io::Result<TlsConn>>(64);
+
+    utils::task::spawn(
+        async move {
+            loop {
+                let (stream, remote) = tokio::select! {
+                    _ = tx.closed() => break,
+                    res = listener.borrow().accept() => match res {
+                        Ok(conn) => conn,
+                        Err(err) => {
+                            log::error!("accept error: {}", err);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            continue;
+                        }
+                    }
+                };
+
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                utils::task::spawn(
+                    async move {
+                        let res = tokio::time::timeout(
+                            HANDSHAKE_TIMEOUT,
+                            acceptor.accept(stream),
+                        )
+                        .await;
+                        match res {
+                            Ok(Ok(stream)) => {
+                                tx.send(Ok(TlsConn { stream, remote }))
+                                    .await
+                                    .ok();
+                            }
+                            Ok(Err(err)) => log::debug!(
+                                "tls handshake with {} failed: {}",
+                                remote,
+                                err
+                            ),
+                            Err(_) => log::debug!(
+                                "tls handshake with {} timed out",
+                                remote
+                            ),
+                        }
+                    },
+                    "tls handshake",
+                );
+            }
+        },
+        "tls accept",
+    );
+
+    hyper::server::accept::from_stream(ReceiverStream(rx))
+}
+
+struct ReceiverStream<T>(mpsc::Receiver<T>);
+
+impl<T> Stream for ReceiverStream<T> {
+    type Item = T;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+pub fn hsts_header(max_age: Duration) -> HeaderValue {
+    HeaderValue::from_str(&format!("max-age={}", max_age.as_secs()))
+        .unwrap()
+}
+
+/// 在明文http端口上把所有请求重定向到https, acme的http-01验证除外
+pub async fn run_redirect_server(
+    addr: SocketAddr,
+    https_port: u16,
+) -> hyper::Result<()> {
+    let make_service = make_service_fn(move |_| async move {
+        Ok::<_, std::convert::Infallible>(service_fn(
+            move |req: Request<Body>| async move {
+                Ok::<_, std::convert::Infallible>(redirect(
+                    &req, https_port,
+                ))
+            },
+        ))
+    });
+
+    let server = hyper::Server::try_bind(&addr)?.serve(make_service);
+    log::info!("redirect http://{} to https", server.local_addr());
+    server
+        .with_graceful_shutdown(async {
+            let resp = SHUTDOWN_NOTIFY.register(5).await.wait().await;
+            log::debug!("redirect server shutdown");
+            resp.ready()
+        })
+        .await
+}
+
+fn redirect(req: &Request<Body>, https_port: u16) -> Response<Body> {
+    if let Some(token) =
+        req.uri().path().strip_prefix(acme::CHALLENGE_PATH_PREFIX)
+    {
+        return match acme::challenge_response(token) {
+            Some(key_auth) => Response::new(Body::from(key_auth)),
+            None => Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
+                .unwrap(),
+        };
+    }
+
+    let host = req
+        .headers()
+        .get(HOST)
+        .and_then(|host| host.to_str().ok())
+        .map(|host| {
+            // 去掉端口, ipv6地址的端口在`]`之后
+            match host.rfind(':') {
+                Some(idx) if !host[idx..].contains(']') => &host[..idx],
+                _ => host,
+            }
+        });
+    let host = match host {
+        Some(host) => host,
+        None => {
+            return Response::builder()
+                .status(StatusCode::BAD_REQUEST)
+                .body(Body::empty())
+                .unwrap()
+        }
+    };
+
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|pq| pq.as_str())
+        .unwrap_or("/");
+    let location = if https_port == 443 {
+        format!("https://{}{}", host, path)
+    } else {
+        format!("https://{}:{}{}", host, https_port, path)
+    };
+
+    match HeaderValue::from_str(&location) {
+        Ok(location) => Response::builder()
+            .status(StatusCode::PERMANENT_REDIRECT)
+            .header(LOCATION, location)
+            .body(Body::empty())
+            .unwrap(),
+        Err(_) => Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .body(Body::empty())
+            .unwrap(),
+    }
+}
@@ -0,0 +1,71 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Local};
+use compact_str::CompactString;
+use tokio::io::AsyncWriteExt;
+
+use config::AccessLogFormat;
+
+use crate::{daily_file, Context};
+
+/// 一次http请求的访问日志
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct AccessRecord {
+    pub time: DateTime<Local>,
+    pub method: CompactString,
+    pub path: String,
+    pub version: CompactString,
+    pub status: u16,
+    /// 从收到请求到返回响应头的时间
+    pub latency_ms: f64,
+    pub bytes: Option<u64>,
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<CompactString>,
+    pub referer: Option<CompactString>,
+}
+
+impl AccessRecord {
+    pub(crate) async fn record(
+        self,
+        cxt: &mut Context,
+    ) -> std::io::Result<()> {
+        let file = daily_file(&mut cxt.access_file, "access-").await?;
+
+        let mut data = match config::get_config_temp().log().access().format()
+        {
+            AccessLogFormat::Json => serde_json::to_vec(&self).unwrap(),
+            AccessLogFormat::Combined => self.combined().into_bytes(),
+        };
+        data.push(b'\n');
+
+        file.write_all(&data).await
+    }
+
+    /// Combined Log Format, 末尾附加以秒为单位的延迟
+    fn combined(&self) -> String {
+        #[inline]
+        fn or_dash(s: &Option<CompactString>) -> &str {
+            s.as_deref().unwrap_or("-")
+        }
+
+        format!(
+            r#"{ip} - - [{time}] "{method} {path} {version}" {status} {bytes} "{referer}" "{ua}" {latency:.3}"#,
+            ip = self
+                .ip
+                .map(|ip| ip.to_string())
+                .unwrap_or_else(|| "-".to_owned()),
+            time = self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            method = self.method,
+            path = self.path.replace('"', "%22"),
+            version = self.version,
+            status = self.status,
+            bytes = self
+                .bytes
+                .map(|bytes| bytes.to_string())
+                .unwrap_or_else(|| "-".to_owned()),
+            referer = or_dash(&self.referer).replace('"', "\\\""),
+            ua = or_dash(&self.user_agent).replace('"', "\\\""),
+            latency = self.latency_ms / 1000.0,
+        )
+    }
+}
@@ -4,6 +4,7 @@ use std::borrow::Cow;
 use std::collections::HashMap;
 use std::fs::create_dir_all;
 use std::ops::Deref;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use arc_swap::ArcSwap;
@@ -13,7 +14,7 @@ use compact_str::CompactString;
 use crossfire::mpsc;
 use crossfire::mpsc::SharedSenderBRecvF;
 use futures::FutureExt;
-use log::Level;
+use log::{Level, LevelFilter};
 use once_cell::sync::Lazy;
 use tokio::fs::{File, OpenOptions};
 use tokio::io::{stdout, AsyncWriteExt, Stdout};
@@ -21,8 +22,24 @@ use tokio::io::{stdout, AsyncWriteExt, Stdout};
 use config::get_config_temp;
 use global_resource::SHUTDOWN_NOTIFY;
 
+pub use crate::access::AccessRecord;
+
+mod access;
+
 static LOGGER: Lazy<Logger> = Lazy::new(Default::default);
 
+/// 还在channel中等待写出的日志数量
+static QUEUED: AtomicUsize = AtomicUsize::new(0);
+
+/// channel满时丢弃的访问日志数量
+static DROPPED_ACCESS: AtomicUsize = AtomicUsize::new(0);
+
+/// sqlx超过`database.warn_time`时会以warn级别打印`sqlx::query`
+static SLOW_QUERIES: AtomicUsize = AtomicUsize::new(0);
+
+/// `log.level`, `log::max_level`至少是warn, 这样慢查询的统计不受日志级别影响
+static LEVEL: AtomicUsize = AtomicUsize::new(LevelFilter::Off as usize);
+
 static LOGGER_FILTER: Lazy<ArcSwap<HashMap<CompactString, Level>>> =
     Lazy::new(|| {
         ArcSwap::from_pointee(
@@ -43,11 +60,26 @@ pub fn init() {
     });
 }
 
+#[inline]
+pub fn queue_len() -> usize {
+    QUEUED.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn dropped_access_count() -> usize {
+    DROPPED_ACCESS.load(Ordering::Relaxed)
+}
+
+#[inline]
+pub fn slow_query_count() -> usize {
+    SLOW_QUERIES.load(Ordering::Relaxed)
+}
+
 #[inline]
 fn set_max_level() {
-    log::set_max_level(
-        config::get_config_temp().log().level().to_level_filter(),
-    )
+    let level = config::get_config_temp().log().level().to_level_filter();
+    LEVEL.store(level as usize, Ordering::Relaxed);
+    log::set_max_level(level.max(LevelFilter::Warn))
 }
 
 impl log::Log for Logger {
@@ -61,7 +93,15 @@ impl log::Log for Logger {
     }
 
     fn log(&self, record: &log::Record) {
-        if self.enabled(record.metadata()) {
+        if record.level() == Level::Warn
+            && record.target() == "sqlx::query"
+        {
+            SLOW_QUERIES.fetch_add(1, Ordering::Relaxed);
+        }
+
+        let level_enabled =
+            record.level() as usize <= LEVEL.load(Ordering::Relaxed);
+        if level_enabled && self.enabled(record.metadata()) {
             let record = Record {
                 metadata: Metadata {
                     level: record.metadata().level(),
@@ -82,7 +122,7 @@ impl log::Log for Logger {
                 line: record.line().unwrap_or_default(),
                 time: Local::now(),
             };
-            self.sender.send(record).expect("failed to send log.");
+            self.send(Message::Log(record));
         }
     }
 
@@ -91,22 +131,52 @@ impl log::Log for Logger {
 }
 
 struct Logger {
-    sender: mpsc::TxBlocking<Record, SharedSenderBRecvF>,
+    sender: mpsc::TxBlocking<Message, SharedSenderBRecvF>,
+}
+
+/// 应用日志和访问日志共用同一个channel
+enum Message {
+    Log(Record),
+    Access(AccessRecord),
+}
+
+/// 写入访问日志, 没有开启`log.access.enable`时直接丢弃.
+/// 在响应的poll中调用, 不能阻塞, channel满时丢弃并计数
+pub fn access(record: AccessRecord) {
+    if *get_config_temp().log().access().enable() {
+        LOGGER.try_send(Message::Access(record));
+    }
 }
 
 impl Logger {
+    #[inline]
+    fn send(&self, msg: Message) {
+        QUEUED.fetch_add(1, Ordering::Relaxed);
+        self.sender.send(msg).expect("failed to send log.");
+    }
+
+    #[inline]
+    fn try_send(&self, msg: Message) {
+        QUEUED.fetch_add(1, Ordering::Relaxed);
+        if self.sender.try_send(msg).is_err() {
+            QUEUED.fetch_sub(1, Ordering::Relaxed);
+            DROPPED_ACCESS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
     pub fn new() -> Self {
         let (tx, rx) = mpsc::bounded_tx_blocking_rx_future(1024);
         Logger::start(rx);
         Logger { sender: tx }
     }
 
-    fn start(rx: mpsc::RxFuture<Record, SharedSenderBRecvF>) {
+    fn start(rx: mpsc::RxFuture<Message, SharedSenderBRecvF>) {
         utils::task::spawn(
             async move {
                 let mut context = Context {
                     stdout: stdout(),
                     file: None,
+                    access_file: None,
                 };
                 let wait_handle = SHUTDOWN_NOTIFY.register(1).await;
 
@@ -130,9 +200,14 @@ impl Logger {
 
     async fn process(
         ctx: &mut Context,
-        rx: &mpsc::RxFuture<Record, SharedSenderBRecvF>,
+        rx: &mpsc::RxFuture<Message, SharedSenderBRecvF>,
     ) {
-        let res = rx.recv().await.unwrap().record(ctx).await;
+        let msg = rx.recv().await.unwrap();
+        QUEUED.fetch_sub(1, Ordering::Relaxed);
+        let res = match msg {
+            Message::Log(record) => record.record(ctx).await,
+            Message::Access(record) => record.record(ctx).await,
+        };
         if let Err(err) = res {
             eprintln!("record error: {:?}", err)
         }
@@ -148,6 +223,30 @@ impl Default for Logger {
 struct Context {
     stdout: Stdout,
     file: Option<(NaiveDate, File)>,
+    access_file: Option<(NaiveDate, File)>,
+}
+
+/// 按天切分日志文件, 日期变化时打开新的文件
+async fn daily_file<'a>(
+    data: &'a mut Option<(NaiveDate, File)>,
+    prefix: &str,
+) -> std::io::Result<&'a mut File> {
+    let now = Local::now().date().naive_local();
+
+    if data.as_ref().map(|(date, _)| now > *date).unwrap_or(true) {
+        let path = get_config_temp().data_path().join("log");
+        create_dir_all(&path)?;
+
+        let file = OpenOptions::new()
+            .write(true)
+            .append(true)
+            .create(true)
+            .open(path.join(format!("{}{}.log", prefix, now)))
+            .await?;
+        *data = Some((now, file));
+    }
+
+    Ok(&mut data.as_mut().unwrap().1)
 }
 
 #[derive(
@@ -231,29 +330,7 @@ impl Record {
         &self,
         data: &mut Option<(NaiveDate, File)>,
     ) -> std::io::Result<()> {
-        #[inline]
-        async fn new_file(date: NaiveDate) -> std::io::Result<File> {
-            let path = get_config_temp().data_path().join("log");
-            let filename = format!("{}.log", date);
-
-            create_dir_all(&path)?;
-
-            OpenOptions::new()
-                .write(true)
-                .append(true)
-                .create(true)
-                .open(path.join(filename))
-                .await
-        }
-
-        let now = Local::now().date().naive_local();
-
-        if data.as_ref().map(|(date, _)| now > *date).unwrap_or(true)
-        {
-            *data = Some((now, new_file(now).await?))
-        }
-
-        let file = &mut data.as_mut().unwrap().1;
+        let file = daily_file(data, "").await?;
 
         let mut data = Vec::with_capacity(100);
         serde_json::to_writer(&mut data, self).unwrap();
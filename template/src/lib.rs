@@ -1,6 +1,10 @@
 #![feature(result_flattening)]
 #![feature(box_syntax)]
 
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::PathBuf;
+
 use anyhow::Context;
 use handlebars::Handlebars;
 use serde::Serialize;
@@ -14,11 +18,13 @@ use crate::template_provider::{
 };
 
 mod helpers;
+mod shortcodes;
 mod template_provider;
 
 pub struct TemplateManager<'reg> {
     hbs: Handlebars<'reg>,
     provider: TemplateProvider,
+    generation: u64,
 }
 
 impl<'reg> TemplateManager<'reg> {
@@ -37,15 +43,18 @@ impl<'reg> TemplateManager<'reg> {
         hbs.register_helper("render_md", box render_md);
         hbs.register_helper("render_md_safe", box render_md_safe);
 
-        let provider = if let Some(path) = config.template() {
-            TemplateProvider::new(LocalFilesProvider(path.clone()))
-        } else {
-            TemplateProvider::new(EmbedTemplateProvider)
-        };
+        let provider = provider(config.template());
         provider
             .load_all(&mut hbs)
             .context("load template provider")?;
-        Ok(TemplateManager { hbs, provider })
+        shortcodes::register_all(&provider)
+            .context("load shortcode templates")?;
+        let generation = generation(&hbs);
+        Ok(TemplateManager {
+            hbs,
+            provider,
+            generation,
+        })
     }
 
     pub fn render<S, D>(
@@ -69,6 +78,38 @@ impl<'reg> TemplateManager<'reg> {
     pub fn provider(&self) -> &TemplateProvider {
         &self.provider
     }
+
+    /// 加载的模板和版本的指纹, 更换主题或升级后会变化
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+fn generation(hbs: &Handlebars) -> u64 {
+    let mut templates = hbs.get_templates().iter().collect::<Vec<_>>();
+    templates.sort_unstable_by_key(|(name, _)| *name);
+    let mut hasher = DefaultHasher::new();
+    env!("CARGO_PKG_VERSION").hash(&mut hasher);
+    for (name, template) in templates {
+        name.hash(&mut hasher);
+        format!("{:?}", template).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+fn provider(path: &Option<PathBuf>) -> TemplateProvider {
+    if let Some(path) = path {
+        TemplateProvider::new(LocalFilesProvider(path.clone()))
+    } else {
+        TemplateProvider::new(EmbedTemplateProvider)
+    }
+}
+
+/// 不需要渲染页面时只加载主题中的shortcode, 比如`maop rerender`
+pub fn load_shortcodes() -> anyhow::Result<()> {
+    let config = config::get_config_temp();
+    shortcodes::register_all(&provider(config.render().template()))
+        .context("load shortcode templates")
 }
 
 #[derive(Serialize)]
@@ -78,3 +119,39 @@ async fn render_test() {
     let tg = TemplateManager::new().unwrap();
     dbg!(tg.hbs.get_templates());
 }
+
+/// 旧的昵称没有转义引号, 不能出现在属性中
+#[test]
+fn comment_nickname_test() {
+    let tm = TemplateManager::new().unwrap();
+    let nickname = r#"x" onmouseover="alert(1)"#;
+    let html = tm
+        .render(
+            "post",
+            &serde_json::json!({
+                "logged": false,
+                "toc": [],
+                "comment_count": 1,
+                "post": {
+                    "id": 1,
+                    "title": "title",
+                    "content_html": "",
+                    "word_count": 0,
+                    "reading_minutes": 0,
+                },
+                "comments": [{
+                    "id": 2,
+                    "deleted": false,
+                    "nickname": nickname,
+                    "email": "a@b.c",
+                    "reply_to": nickname,
+                    "content": "content",
+                    "children": [],
+                }],
+            }),
+        )
+        .unwrap();
+    assert!(!html.contains("data-nickname"));
+    assert!(html
+        .contains(&format!(r#"<span class="nickname">{}</span>"#, nickname)));
+}
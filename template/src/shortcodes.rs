@@ -0,0 +1,55 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use handlebars::Handlebars;
+use serde::Serialize;
+use utils::markdown::shortcode::{self, Args, Shortcode};
+
+use crate::template_provider::TemplateProvider;
+
+/// 主题中的`shortcodes/<name>.hbs`, 模板中可以使用`args`和`body`
+struct TemplateShortcode {
+    hbs: Arc<Handlebars<'static>>,
+    name: String,
+}
+
+#[derive(Serialize)]
+struct Context<'a> {
+    args: &'a Args,
+    body: Option<&'a str>,
+}
+
+impl Shortcode for TemplateShortcode {
+    fn render(&self, args: &Args, body: Option<&str>) -> anyhow::Result<String> {
+        self.hbs
+            .render(&self.name, &Context { args, body })
+            .with_context(|| format!("render shortcode template `{}`", self.name))
+    }
+}
+
+/// 和页面模板不同, 这里使用handlebars默认的html转义,
+/// 参数来自文章正文, 内容需要用`{{{body}}}`输出
+pub fn register_all(provider: &TemplateProvider) -> anyhow::Result<()> {
+    let templates = provider.shortcodes()?;
+    if templates.is_empty() {
+        return Ok(());
+    }
+
+    let mut hbs = Handlebars::new();
+    for (name, body) in &templates {
+        hbs.register_template_string(name, body)
+            .with_context(|| format!("load shortcode template `{}`", name))?;
+    }
+    let hbs = Arc::new(hbs);
+    for (name, _) in templates {
+        log::debug!("register shortcode `{}` from template", name);
+        shortcode::register(
+            name.clone(),
+            TemplateShortcode {
+                hbs: Arc::clone(&hbs),
+                name,
+            },
+        );
+    }
+    Ok(())
+}
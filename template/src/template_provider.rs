@@ -8,6 +8,8 @@ use tokio::fs::OpenOptions;
 use tokio::io::AsyncReadExt;
 use walkdir::WalkDir;
 
+const SHORTCODES_DIR: &str = "shortcodes";
+
 #[derive(rust_embed::RustEmbed)]
 #[folder = "$CARGO_MANIFEST_DIR/small"]
 pub struct EmbedTemplateProvider;
@@ -25,6 +27,15 @@ pub trait Provider {
         &self,
         path: &str,
     ) -> anyhow::Result<Option<Cow<'static, [u8]>>>;
+
+    /// `shortcodes`目录下的模板, 返回名字和内容
+    fn shortcodes(&self) -> anyhow::Result<Vec<(String, String)>>;
+}
+
+/// 去掉`.hbs`后缀作为shortcode的名字
+fn shortcode_name(path: &Path) -> Option<String> {
+    let file_name = path.file_name()?.to_str()?;
+    file_name.strip_suffix(".hbs").map(str::to_owned)
 }
 
 #[async_trait::async_trait]
@@ -34,6 +45,9 @@ impl Provider for EmbedTemplateProvider {
         hbs: &mut Handlebars<'reg>,
     ) -> anyhow::Result<()> {
         Self::iter().try_for_each(|path| {
+            if Path::new(&*path).starts_with(SHORTCODES_DIR) {
+                return Ok(());
+            }
             let file_name = Path::new(&*path)
                 .file_name()
                 .unwrap()
@@ -57,6 +71,21 @@ impl Provider for EmbedTemplateProvider {
     ) -> anyhow::Result<Option<Cow<'static, [u8]>>> {
         Ok(Self::get(path).map(|file| file.data))
     }
+
+    fn shortcodes(&self) -> anyhow::Result<Vec<(String, String)>> {
+        Self::iter()
+            .filter(|path| Path::new(path.as_ref()).starts_with(SHORTCODES_DIR))
+            .filter_map(|path| {
+                let name = shortcode_name(Path::new(&*path))?;
+                let file = Self::get(&*path)?;
+                Some(
+                    String::from_utf8(file.data.into_owned())
+                        .map(|body| (name, body))
+                        .map_err(Into::into),
+                )
+            })
+            .collect()
+    }
 }
 
 #[async_trait::async_trait]
@@ -71,6 +100,7 @@ impl Provider for LocalFilesProvider {
             .filter_map(|e| e.ok())
             .filter(|e| e.file_type().is_file())
             .filter(|e| e.path().ends_with(".hbs"))
+            .filter(|e| !e.path().starts_with(self.0.join(SHORTCODES_DIR)))
             .try_for_each(|e| {
                 let file_name = e.file_name().to_string_lossy();
                 let body = read_to_string(e.path())?;
@@ -100,6 +130,23 @@ impl Provider for LocalFilesProvider {
             },
         }
     }
+
+    fn shortcodes(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let dir = self.0.join(SHORTCODES_DIR);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        WalkDir::new(dir)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+            .filter_map(|e| {
+                let name = shortcode_name(e.path())?;
+                Some(read_to_string(e.path()).map(|body| (name, body)).map_err(Into::into))
+            })
+            .collect()
+    }
 }
 
 pub struct TemplateProvider(pub Box<dyn Provider + Sync + Send>);
@@ -133,6 +133,16 @@ impl<'a> Wheel<'a> {
         slot.tasks.push(TaskWrapper { task, round });
     }
 
+    /// 所有层级中等待执行的任务数量, 不包括正在执行的任务
+    pub fn task_count(&self) -> usize {
+        self.slots.iter().map(|slot| slot.tasks.len()).sum::<usize>()
+            + self
+                .next_wheel
+                .as_ref()
+                .map(|wheel| wheel.task_count())
+                .unwrap_or(0)
+    }
+
     #[async_recursion::async_recursion]
     pub async fn roll(&self) {
         let roll_next = self.next_index();
@@ -3,6 +3,7 @@ pub use simple_i18n::{i18n, lang};
 pub mod markdown;
 pub mod notify;
 pub mod password_hash;
+pub mod sd_notify;
 pub mod task;
 pub mod unit;
 
@@ -0,0 +1,180 @@
+use std::fmt::Write;
+use std::ops::RangeInclusive;
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use pulldown_cmark::escape::escape_html;
+use syntect::highlighting::ThemeSet;
+use syntect::html::{
+    css_for_theme_with_class_style, line_tokens_to_classed_spans,
+    ClassStyle,
+};
+use syntect::parsing::{ParseState, Scope, ScopeStack, SyntaxSet};
+use syntect::util::LinesWithEndings;
+
+static SYNTAX_SET: Lazy<SyntaxSet> =
+    Lazy::new(SyntaxSet::load_defaults_newlines);
+static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+
+/// 所有class都带上前缀, 避免和主题自己的样式冲突
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+/// 解析```` ```rust {3,5-7} ````这样的info, 返回语言和需要强调的行
+pub fn parse_info(info: &str) -> (&str, Vec<RangeInclusive<usize>>) {
+    let info = info.trim();
+    let (lang, rest) = match info.find(|c: char| c.is_whitespace() || c == '{')
+    {
+        Some(idx) => (&info[..idx], &info[idx..]),
+        None => (info, ""),
+    };
+    let marks = rest
+        .trim()
+        .strip_prefix('{')
+        .and_then(|rest| rest.split('}').next())
+        .map(|ranges| {
+            ranges
+                .split(',')
+                .filter_map(|range| {
+                    let range = range.trim();
+                    match range.split_once('-') {
+                        Some((start, end)) => Some(
+                            start.trim().parse().ok()?
+                                ..=end.trim().parse().ok()?,
+                        ),
+                        None => {
+                            let line = range.parse().ok()?;
+                            Some(line..=line)
+                        }
+                    }
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    (lang, marks)
+}
+
+/// 每一行都包在`<span class="code-line">`中, 以便加上行号和强调.
+/// 跨行的scope会在行尾关闭, 下一行开头重新打开
+pub fn highlight(
+    code: &str,
+    info: &str,
+    line_numbers: bool,
+) -> anyhow::Result<String> {
+    let (lang, marks) = parse_info(info);
+    let syntax = SYNTAX_SET
+        .find_syntax_by_token(lang)
+        .unwrap_or_else(|| SYNTAX_SET.find_syntax_plain_text());
+    let mut parse_state = ParseState::new(syntax);
+    let mut stack = ScopeStack::new();
+
+    let mut html = String::with_capacity(code.len() * 3);
+    html.push_str(r#"<pre class="hl-code"#);
+    if line_numbers {
+        html.push_str(" code-numbered");
+    }
+    html.push('"');
+    if !lang.is_empty() {
+        html.push_str(r#" data-lang=""#);
+        escape_html(&mut html, lang)?;
+        html.push('"');
+    }
+    html.push_str("><code>");
+
+    for (idx, line) in LinesWithEndings::from(code).enumerate() {
+        let marked = marks.iter().any(|range| range.contains(&(idx + 1)));
+        html.push_str(if marked {
+            r#"<span class="code-line code-line-mark">"#
+        } else {
+            r#"<span class="code-line">"#
+        });
+        for scope in stack.as_slice() {
+            open_span(&mut html, *scope);
+        }
+
+        let ops = parse_state
+            .parse_line(line, &SYNTAX_SET)
+            .context("highlight code")?;
+        let (spans, _) =
+            line_tokens_to_classed_spans(line, &ops, CLASS_STYLE, &mut stack)
+                .context("highlight code")?;
+        html.push_str(&spans);
+
+        (0..=stack.len()).for_each(|_| html.push_str("</span>"));
+    }
+
+    html.push_str("</code></pre>\n");
+    Ok(html)
+}
+
+fn open_span(html: &mut String, scope: Scope) {
+    html.push_str(r#"<span class=""#);
+    let classes = scope.build_string();
+    for (idx, atom) in classes.split('.').enumerate() {
+        if idx != 0 {
+            html.push(' ');
+        }
+        html.push_str("hl-");
+        html.push_str(atom);
+    }
+    html.push_str(r#"">"#);
+}
+
+pub fn themes() -> impl Iterator<Item = &'static str> {
+    THEME_SET.themes.keys().map(String::as_str)
+}
+
+/// 生成主题的css, 以及行号和强调行的样式
+pub fn css(theme: &str) -> anyhow::Result<String> {
+    let theme = THEME_SET.themes.get(theme).with_context(|| {
+        format!(
+            "unknown theme `{}`, available: {}",
+            theme,
+            themes().collect::<Vec<_>>().join(", ")
+        )
+    })?;
+    let mut css = css_for_theme_with_class_style(theme, CLASS_STYLE)
+        .context("generate theme css")?;
+
+    css.push_str(".hl-code .code-line {\n display: block;\n}\n\n");
+    let mark = theme
+        .settings
+        .line_highlight
+        .map(|c| format!("rgba({}, {}, {}, {:.2})", c.r, c.g, c.b, c.a as f32 / 255.0))
+        .unwrap_or_else(|| "rgba(255, 235, 59, 0.25)".to_owned());
+    writeln!(
+        css,
+        ".hl-code .code-line-mark {{\n background-color: {};\n}}\n",
+        mark
+    )?;
+    css.push_str(
+        ".hl-code.code-numbered {\n counter-reset: code-line;\n}\n\n\
+         .hl-code.code-numbered .code-line::before {\n \
+         counter-increment: code-line;\n \
+         content: counter(code-line);\n \
+         display: inline-block;\n \
+         width: 2em;\n \
+         margin-right: 1em;\n \
+         text-align: right;\n \
+         opacity: 0.5;\n \
+         user-select: none;\n}\n",
+    );
+    Ok(css)
+}
+
+#[test]
+fn parse_info_test() {
+    assert_eq!(parse_info("rust"), ("rust", vec![]));
+    assert_eq!(parse_info("rust {3,5-7}"), ("rust", vec![3..=3, 5..=7]));
+    assert_eq!(parse_info("js{1}"), ("js", vec![1..=1]));
+    assert_eq!(parse_info(""), ("", vec![]));
+}
+
+#[test]
+fn highlight_test() {
+    let html = highlight("/* a\nb */\nlet a = 1;\n", "rust {2}", true).unwrap();
+    assert!(html.starts_with(r#"<pre class="hl-code code-numbered" data-lang="rust">"#));
+    assert_eq!(html.matches(r#"<span class="code-line"#).count(), 3);
+    assert_eq!(html.matches("code-line-mark").count(), 1);
+    assert_eq!(html.matches("<span").count(), html.matches("</span>").count());
+    assert!(css("InspiredGitHub").unwrap().contains(".hl-code"));
+}
@@ -0,0 +1,154 @@
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// `[[post:42]]`和`[[post:42|text]]`渲染成html注释,
+/// 展示页面时再换成文章当前的地址和标题
+const MARKER_OPEN: &str = "<!--post:";
+const MARKER_CLOSE: &str = "-->";
+
+/// 文本被pulldown-cmark在`[`和`]`处切开了, 先合并相邻的文本再查找
+pub fn post_links<'a>(events: Vec<Event<'a>>) -> Vec<Event<'a>> {
+    let mut out = Vec::with_capacity(events.len());
+    let mut text = String::new();
+    let mut in_code_block = false;
+    for event in events {
+        match event {
+            Event::Text(s) if !in_code_block => text.push_str(&s),
+            event => {
+                flush(&mut text, &mut out);
+                match &event {
+                    Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+                    Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+                    _ => {}
+                }
+                out.push(event);
+            }
+        }
+    }
+    flush(&mut text, &mut out);
+    out
+}
+
+fn flush(text: &mut String, out: &mut Vec<Event<'_>>) {
+    if text.is_empty() {
+        return;
+    }
+    let mut rest = text.as_str();
+    while let Some(start) = rest.find("[[post:") {
+        let parsed = rest[start + 2..].find("]]").and_then(|end| {
+            let inner = &rest[start + 2..start + 2 + end];
+            parse(inner.strip_prefix("post:")?).map(|link| (link, start + 2 + end + 2))
+        });
+        match parsed {
+            Some(((id, label), end)) => {
+                if start > 0 {
+                    out.push(Event::Text(CowStr::from(rest[..start].to_owned())));
+                }
+                let mut marker = format!("{}{}", MARKER_OPEN, id);
+                if let Some(label) = label {
+                    marker.push('|');
+                    escape_html(&mut marker, label).ok();
+                }
+                marker.push_str(MARKER_CLOSE);
+                out.push(Event::Html(CowStr::from(marker)));
+                rest = &rest[end..];
+            }
+            None => {
+                out.push(Event::Text(CowStr::from(rest[..start + 2].to_owned())));
+                rest = &rest[start + 2..];
+            }
+        }
+    }
+    if !rest.is_empty() {
+        out.push(Event::Text(CowStr::from(rest.to_owned())));
+    }
+    text.clear();
+}
+
+/// `42`或`42|text`
+fn parse(s: &str) -> Option<(u32, Option<&str>)> {
+    let (id, label) = match s.split_once('|') {
+        Some((id, label)) => (id, Some(label.trim()).filter(|l| !l.is_empty())),
+        None => (s, None),
+    };
+    Some((id.trim().parse().ok()?, label))
+}
+
+fn markers(html: &str) -> impl Iterator<Item = (usize, usize, u32, Option<&str>)> {
+    let mut offset = 0;
+    std::iter::from_fn(move || loop {
+        let start = offset + html[offset..].find(MARKER_OPEN)?;
+        let inner_start = start + MARKER_OPEN.len();
+        let end = inner_start + html[inner_start..].find(MARKER_CLOSE)?;
+        offset = end + MARKER_CLOSE.len();
+        if let Some((id, label)) = parse(&html[inner_start..end]) {
+            return Some((start, offset, id, label));
+        }
+    })
+}
+
+/// html中链接到的文章, 已去重
+pub fn post_ids(html: &str) -> Vec<u32> {
+    let mut ids = markers(html).map(|(_, _, id, _)| id).collect::<Vec<_>>();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// `lookup`返回文章的地址和标题, 找不到的文章只保留文字
+pub fn resolve<F>(html: &str, lookup: F) -> String
+where
+    F: Fn(u32) -> Option<(String, String)>,
+{
+    let mut out = String::with_capacity(html.len());
+    let mut last = 0;
+    for (start, end, id, label) in markers(html) {
+        out.push_str(&html[last..start]);
+        match lookup(id) {
+            Some((url, title)) => {
+                out.push_str(r#"<a class="post-link" href=""#);
+                escape_html(&mut out, &url).ok();
+                out.push_str(r#"">"#);
+                match label {
+                    Some(label) => out.push_str(label),
+                    None => {
+                        escape_html(&mut out, &title).ok();
+                    }
+                }
+                out.push_str("</a>");
+            }
+            None => {
+                out.push_str(r#"<span class="post-link post-link-missing">"#);
+                match label {
+                    Some(label) => out.push_str(label),
+                    None => out.push_str(&format!("[[post:{}]]", id)),
+                }
+                out.push_str("</span>");
+            }
+        }
+        last = end;
+    }
+    out.push_str(&html[last..]);
+    out
+}
+
+#[test]
+fn post_links_test() {
+    use pulldown_cmark::{html, Parser};
+
+    let events = Parser::new("see [[post:1]], [[post:2|Q & A]] and [[post:x]] `[[post:3]]`")
+        .collect();
+    let mut output = String::new();
+    html::push_html(&mut output, post_links(events).into_iter());
+    assert_eq!(post_ids(&output), [1, 2]);
+
+    let resolved = resolve(&output, |id| {
+        (id == 1).then(|| ("/post/1".to_owned(), "A & B".to_owned()))
+    });
+    assert_eq!(
+        resolved,
+        "<p>see <a class=\"post-link\" href=\"/post/1\">A &amp; B</a>, \
+         <span class=\"post-link post-link-missing\">Q &amp; A</span> \
+         and [[post:x]] <code>[[post:3]]</code></p>\n"
+    );
+}
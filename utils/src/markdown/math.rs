@@ -0,0 +1,227 @@
+use std::borrow::Cow;
+
+use latex2mathml::{latex_to_mathml, DisplayStyle};
+use pulldown_cmark::escape::escape_html;
+
+/// 公式在解析markdown之前被替换成占位符, 避免`_`和`*`被当成强调.
+/// 用私有区的字符作为分隔, 正文中基本不会出现
+const OPEN: char = '\u{E000}';
+const CLOSE: char = '\u{E001}';
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Math {
+    display: bool,
+    tex: String,
+    /// 包括`$`的原文, 出现在代码中时原样还原
+    raw: String,
+}
+
+impl Math {
+    pub fn to_mathml(&self) -> String {
+        let style = if self.display {
+            DisplayStyle::Block
+        } else {
+            DisplayStyle::Inline
+        };
+        match latex_to_mathml(&self.tex, style) {
+            Ok(mathml) => mathml,
+            Err(err) => {
+                log::warn!("invalid math `{}`: {}", self.tex, err);
+                let mut html = String::from(r#"<code class="math-error">"#);
+                escape_html(&mut html, &self.raw).ok();
+                html.push_str("</code>");
+                html
+            }
+        }
+    }
+}
+
+pub enum Piece<'a> {
+    Text(&'a str),
+    Math(&'a Math),
+}
+
+/// 找出`$inline$`和`$$display$$`, 跳过fenced code block和行内代码
+pub fn extract(src: &str) -> (String, Vec<Math>) {
+    let mut out = String::with_capacity(src.len());
+    let mut maths = Vec::new();
+    let mut pending = String::new();
+    let mut fence: Option<(char, usize)> = None;
+
+    for line in src.split_inclusive('\n') {
+        match fence {
+            Some(open) => {
+                out.push_str(line);
+                if super::closes_fence(line, open) {
+                    fence = None;
+                }
+            }
+            None => match super::fence(line) {
+                Some(open) => {
+                    scan(&pending, &mut out, &mut maths);
+                    pending.clear();
+                    out.push_str(line);
+                    fence = Some(open);
+                }
+                None => pending.push_str(line),
+            },
+        }
+    }
+    scan(&pending, &mut out, &mut maths);
+
+    (out, maths)
+}
+
+fn scan(text: &str, out: &mut String, maths: &mut Vec<Math>) {
+    let bytes = text.as_bytes();
+    let mut idx = 0;
+    let mut last = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'`' => {
+                let len = bytes[idx..].iter().take_while(|b| **b == b'`').count();
+                idx = closing_backticks(bytes, idx + len, len).unwrap_or(idx + len);
+            }
+            b'$' => match match_math(text, idx) {
+                Some((end, math)) => {
+                    out.push_str(&text[last..idx]);
+                    out.push(OPEN);
+                    out.push_str(&maths.len().to_string());
+                    out.push(CLOSE);
+                    maths.push(math);
+                    idx = end;
+                    last = end;
+                }
+                None => idx += 1,
+            },
+            _ => idx += 1,
+        }
+    }
+    out.push_str(&text[last..]);
+}
+
+/// 返回和开头长度相同的反引号之后的位置
+fn closing_backticks(bytes: &[u8], mut idx: usize, len: usize) -> Option<usize> {
+    while idx < bytes.len() {
+        if bytes[idx] == b'`' {
+            let run = bytes[idx..].iter().take_while(|b| **b == b'`').count();
+            if run == len {
+                return Some(idx + run);
+            }
+            idx += run;
+        } else {
+            idx += 1;
+        }
+    }
+    None
+}
+
+/// 行内公式的`$`内侧不能是空白, 结尾的`$`后面不能是数字, 避免`$5 and $10`被当成公式
+fn match_math(text: &str, start: usize) -> Option<(usize, Math)> {
+    let rest = &text[start..];
+    if let Some(body) = rest.strip_prefix("$$") {
+        let end = body.find("$$")?;
+        let tex = body[..end].trim();
+        if tex.is_empty() {
+            return None;
+        }
+        let end = start + 2 + end + 2;
+        return Some((
+            end,
+            Math {
+                display: true,
+                tex: tex.to_owned(),
+                raw: text[start..end].to_owned(),
+            },
+        ));
+    }
+
+    let body = &rest[1..];
+    if body.starts_with(char::is_whitespace) {
+        return None;
+    }
+    let bytes = body.as_bytes();
+    let mut idx = 0;
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'\\' => idx += 2,
+            b'\n' if body[idx + 1..].starts_with('\n') => return None,
+            b'$' if idx > 0
+                && !bytes[idx - 1].is_ascii_whitespace()
+                && !bytes.get(idx + 1).map_or(false, u8::is_ascii_digit) =>
+            {
+                let end = start + 1 + idx + 1;
+                return Some((
+                    end,
+                    Math {
+                        display: false,
+                        tex: body[..idx].to_owned(),
+                        raw: text[start..end].to_owned(),
+                    },
+                ));
+            }
+            _ => idx += 1,
+        }
+    }
+    None
+}
+
+/// 按占位符切分文本
+pub fn pieces<'a>(text: &'a str, maths: &'a [Math]) -> Vec<Piece<'a>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while let Some(open) = rest.find(OPEN) {
+        let close = match rest[open..].find(CLOSE) {
+            Some(close) => open + close,
+            None => break,
+        };
+        match rest[open + OPEN.len_utf8()..close]
+            .parse::<usize>()
+            .ok()
+            .and_then(|idx| maths.get(idx))
+        {
+            Some(math) => {
+                if open > 0 {
+                    pieces.push(Piece::Text(&rest[..open]));
+                }
+                pieces.push(Piece::Math(math));
+            }
+            None => pieces.push(Piece::Text(&rest[..close + CLOSE.len_utf8()])),
+        }
+        rest = &rest[close + CLOSE.len_utf8()..];
+    }
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    pieces
+}
+
+/// 代码和html中的占位符还原成原文
+pub fn restore<'a>(text: &'a str, maths: &[Math]) -> Cow<'a, str> {
+    if !text.contains(OPEN) {
+        return Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    for piece in pieces(text, maths) {
+        match piece {
+            Piece::Text(text) => out.push_str(text),
+            Piece::Math(math) => out.push_str(&math.raw),
+        }
+    }
+    Cow::Owned(out)
+}
+
+#[test]
+fn extract_test() {
+    let (out, maths) = extract("a $x_1$ b $$\\sum_i i$$ `$c$`\n```\n$d$\n```\n$5 and $10\n");
+    assert_eq!(maths.len(), 2);
+    assert_eq!(maths[0].tex, "x_1");
+    assert!(!maths[0].display);
+    assert_eq!(maths[1].tex, "\\sum_i i");
+    assert!(maths[1].display);
+    assert!(out.contains("`$c$`"));
+    assert!(out.contains("```\n$d$\n```"));
+    assert!(out.ends_with("$5 and $10\n"));
+    assert_eq!(restore(&out, &maths), "a $x_1$ b $$\\sum_i i$$ `$c$`\n```\n$d$\n```\n$5 and $10\n");
+}
@@ -0,0 +1,217 @@
+use std::borrow::Cow;
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
+
+use crate::markdown::math::{Math, Piece};
+use crate::markdown::sanitize::SanitizePolicy;
+use crate::markdown::toc::TocEntry;
+
+pub mod highlight;
+pub mod link;
+pub mod math;
+pub mod sanitize;
+pub mod shortcode;
+pub mod summary;
+pub mod toc;
+
+/// 由config在加载和刷新时设置, 避免utils依赖config
+#[derive(Debug, Clone, Default)]
+pub struct MarkdownOptions {
+    pub highlight: bool,
+    pub line_numbers: bool,
+    /// `$inline$`和`$$display$$`渲染成MathML
+    pub math: bool,
+    /// 标题前加上指向自己的链接
+    pub heading_anchors: bool,
+    /// 为`None`时文章中可以使用任意html
+    pub post_policy: Option<SanitizePolicy>,
+    pub comment_policy: SanitizePolicy,
+}
+
+static OPTIONS: Lazy<RwLock<Arc<MarkdownOptions>>> =
+    Lazy::new(Default::default);
+
+pub fn set_options(options: MarkdownOptions) {
+    *OPTIONS.write().unwrap() = Arc::new(options);
+}
+
+#[inline]
+fn options() -> Arc<MarkdownOptions> {
+    Arc::clone(&OPTIONS.read().unwrap())
+}
+
+/// 把fenced code block替换成高亮后的html, 公式的占位符替换成MathML
+fn events<'a>(
+    parser: Parser<'a>,
+    options: &MarkdownOptions,
+    maths: &[Math],
+) -> anyhow::Result<Vec<Event<'a>>> {
+    let mut events = Vec::new();
+    let mut code: Option<(CowStr<'a>, String)> = None;
+    let mut in_code_block = false;
+    for event in parser {
+        match event {
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info)))
+                if options.highlight =>
+            {
+                code = Some((info, String::new()))
+            }
+            Event::Text(text) if code.is_some() => code
+                .as_mut()
+                .unwrap()
+                .1
+                .push_str(&math::restore(&text, maths)),
+            Event::End(Tag::CodeBlock(_)) if code.is_some() => {
+                let (info, buf) = code.take().unwrap();
+                events.push(Event::Html(CowStr::from(highlight::highlight(
+                    &buf,
+                    &info,
+                    options.line_numbers,
+                )?)));
+            }
+            event if maths.is_empty() => events.push(event),
+            Event::Start(Tag::CodeBlock(kind)) => {
+                in_code_block = true;
+                events.push(Event::Start(Tag::CodeBlock(kind)))
+            }
+            Event::End(Tag::CodeBlock(kind)) => {
+                in_code_block = false;
+                events.push(Event::End(Tag::CodeBlock(kind)))
+            }
+            Event::Text(text) if in_code_block => events.push(Event::Text(
+                CowStr::from(math::restore(&text, maths).into_owned()),
+            )),
+            Event::Text(text) => {
+                for piece in math::pieces(&text, maths) {
+                    events.push(match piece {
+                        Piece::Text(text) => {
+                            Event::Text(CowStr::from(text.to_owned()))
+                        }
+                        Piece::Math(math) => {
+                            Event::Html(CowStr::from(math.to_mathml()))
+                        }
+                    })
+                }
+            }
+            Event::Code(text) => events.push(Event::Code(CowStr::from(
+                math::restore(&text, maths).into_owned(),
+            ))),
+            Event::Html(text) => events.push(Event::Html(CowStr::from(
+                math::restore(&text, maths).into_owned(),
+            ))),
+            event => events.push(event),
+        }
+    }
+    Ok(events)
+}
+
+fn render_events<'a>(
+    src: &'a str,
+    options: &MarkdownOptions,
+    maths: &[Math],
+) -> anyhow::Result<Vec<Event<'a>>> {
+    events(Parser::new_ext(src, Options::all()), options, maths)
+}
+
+fn prepare(s: &str, options: &MarkdownOptions) -> (Cow<'_, str>, Vec<Math>) {
+    if options.math {
+        let (src, maths) = math::extract(s);
+        (Cow::Owned(src), maths)
+    } else {
+        (Cow::Borrowed(s), Vec::new())
+    }
+}
+
+/// fenced code block的开始行, 返回标记字符和长度
+fn fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = line.trim_start_matches(' ');
+    if line.len() - trimmed.len() >= 4 {
+        return None;
+    }
+    let marker = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let run = trimmed.chars().take_while(|c| *c == marker).count();
+    (run >= 3).then(|| (marker, run))
+}
+
+fn closes_fence(line: &str, (marker, len): (char, usize)) -> bool {
+    matches!(fence(line), Some((c, run))
+        if c == marker && run >= len && line.trim_start_matches(' ')[run..].trim().is_empty())
+}
+
+fn write_html<'a>(
+    events: impl Iterator<Item = Event<'a>>,
+    capacity: usize,
+) -> anyhow::Result<String> {
+    let mut output = Vec::with_capacity(capacity);
+    html::write_html(&mut output, events).context("render markdown")?;
+    Ok(unsafe { String::from_utf8_unchecked(output) })
+}
+
+/// 正文和shortcode的内容共用, 返回的标题还没有加上id
+fn post_events<'a>(
+    src: &'a str,
+    options: &MarkdownOptions,
+    maths: &[Math],
+    blocks: &[String],
+) -> anyhow::Result<Vec<Event<'a>>> {
+    let events = render_events(src, options, maths)?;
+    let events = link::post_links(shortcode::expand(events, blocks));
+    let target = options
+        .post_policy
+        .as_ref()
+        .and_then(|policy| policy.external_target.as_deref());
+    Ok(sanitize::external_links(events, target))
+}
+
+/// shortcode的内容和正文一样渲染, 但不参与目录
+fn render_body(s: &str) -> anyhow::Result<String> {
+    let options = options();
+    let (src, blocks) = shortcode::extract(s, render_body)?;
+    let (src, maths) = prepare(&src, &options);
+    let events = post_events(&src, &options, &maths, &blocks)?;
+    write_html(events.into_iter(), src.len() * 2)
+}
+
+/// 文章使用, 标题会带上id, 支持shortcode和`[[post:id]]`
+pub fn render(s: &str) -> anyhow::Result<String> {
+    render_with_toc(s).map(|(html, _)| html)
+}
+
+/// 同时返回目录, shortcode内的标题不在目录中
+pub fn render_with_toc(s: &str) -> anyhow::Result<(String, Vec<TocEntry>)> {
+    let options = options();
+    let (src, blocks) = shortcode::extract(s, render_body)?;
+    let (src, maths) = prepare(&src, &options);
+    let events = post_events(&src, &options, &maths, &blocks)?;
+    let (events, toc) = toc::headings(events, options.heading_anchors);
+    let html = write_html(events.into_iter(), src.len() * 2)?;
+    let html = match &options.post_policy {
+        Some(policy) => policy.clean(&html),
+        None => html,
+    };
+    Ok((html, toc))
+}
+
+/// 评论使用, 按评论的规则清理html
+pub fn render_safe(s: &str) -> anyhow::Result<String> {
+    let options = options();
+    let policy = &options.comment_policy;
+    let (src, maths) = prepare(s, &options);
+    let events = sanitize::external_links(
+        render_events(&src, &options, &maths)?,
+        policy.external_target.as_deref(),
+    );
+    let html = write_html(events.into_iter(), src.len() * 2)?;
+    Ok(policy.clean(&html))
+}
+
+/// 昵称之类的纯文本, 不保留任何标签
+#[inline]
+pub fn html_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    pulldown_cmark::escape::escape_html(&mut out, s).ok();
+    out
+}
@@ -0,0 +1,131 @@
+use std::collections::{HashMap, HashSet};
+
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// 文章和评论各自的清理规则, 由config转换而来
+#[derive(Debug, Clone, Default)]
+pub struct SanitizePolicy {
+    /// 在ammonia默认允许的基础上增加的标签
+    pub tags: Vec<String>,
+    /// 标签允许的属性, `*`表示所有标签
+    pub attributes: HashMap<String, Vec<String>>,
+    /// 为空时使用ammonia默认的
+    pub url_schemes: Vec<String>,
+    /// 加到所有链接上的`rel`
+    pub link_rel: Option<String>,
+    /// 外部链接的`target`, 比如`_blank`
+    pub external_target: Option<String>,
+    /// 文章中的`[[post:id]]`标记是html注释, 需要保留
+    pub keep_comments: bool,
+}
+
+impl SanitizePolicy {
+    /// 代码高亮的class和公式总是保留
+    fn builder(&self) -> ammonia::Builder<'_> {
+        let mut builder = ammonia::Builder::default();
+        builder
+            .add_tags(MATHML_TAGS)
+            .add_tags(&self.tags)
+            .add_tag_attributes("math", &["display", "xmlns"])
+            .add_tag_attributes("mo", &["stretchy", "fence", "separator", "lspace", "rspace"])
+            .add_tag_attributes("mfrac", &["linethickness"])
+            .add_tag_attributes("mover", &["accent"])
+            .add_tag_attributes("munder", &["accentunder"])
+            .add_tag_attributes("mtable", &["columnalign", "rowalign"])
+            .add_tag_attributes("mstyle", &["displaystyle", "scriptlevel"])
+            .add_tag_attributes("mspace", &["width"])
+            .add_tag_attributes("annotation", &["encoding"])
+            .add_generic_attributes(&["mathvariant", "class"])
+            .link_rel(self.link_rel.as_deref())
+            .strip_comments(!self.keep_comments);
+        if self.external_target.is_some() {
+            builder.add_tag_attributes("a", &["target"]);
+        }
+        for (tag, attributes) in &self.attributes {
+            // 设置了`link_rel`时ammonia不允许再保留`rel`
+            let attributes = attributes
+                .iter()
+                .filter(|attribute| self.link_rel.is_none() || *attribute != "rel");
+            if tag == "*" {
+                builder.add_generic_attributes(attributes);
+            } else {
+                builder.add_tag_attributes(tag, attributes);
+            }
+        }
+        if !self.url_schemes.is_empty() {
+            builder.url_schemes(self.url_schemes.iter().map(String::as_str).collect::<HashSet<_>>());
+        }
+        builder
+    }
+
+    pub fn clean(&self, html: &str) -> String {
+        self.builder().clean(html).to_string()
+    }
+}
+
+/// 指向其他站点的http(s)链接
+fn is_external(url: &str) -> bool {
+    let prefix = url.get(..8).unwrap_or(url).to_ascii_lowercase();
+    url.starts_with("//") || prefix.starts_with("http://") || prefix.starts_with("https://")
+}
+
+/// 外部链接加上`target`, 清理时`rel`会被`link_rel`替换
+pub fn external_links<'a>(events: Vec<Event<'a>>, target: Option<&str>) -> Vec<Event<'a>> {
+    let target = match target {
+        Some(target) => target,
+        None => return events,
+    };
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Start(Tag::Link(_, url, title)) if is_external(&url) => {
+                let mut html = String::from(r#"<a href=""#);
+                escape_href(&mut html, &url).ok();
+                if !title.is_empty() {
+                    html.push_str(r#"" title=""#);
+                    escape_html(&mut html, &title).ok();
+                }
+                html.push_str(r#"" target=""#);
+                escape_html(&mut html, target).ok();
+                html.push_str(r#"" rel="noopener noreferrer">"#);
+                Event::Html(CowStr::from(html))
+            }
+            event => event,
+        })
+        .collect()
+}
+
+const MATHML_TAGS: &[&str] = &[
+    "math", "semantics", "annotation", "mrow", "mi", "mn", "mo", "ms",
+    "mtext", "mspace", "mfrac", "msqrt", "mroot", "msub", "msup",
+    "msubsup", "munder", "mover", "munderover", "mtable", "mtr", "mtd",
+    "mstyle", "mpadded", "mphantom", "menclose",
+];
+
+#[test]
+fn sanitize_test() {
+    let comment = SanitizePolicy {
+        link_rel: Some("nofollow ugc".to_owned()),
+        external_target: Some("_blank".to_owned()),
+        ..Default::default()
+    };
+    let events = pulldown_cmark::Parser::new("[a](https://example.com) [b](/post/1) <script>x</script>")
+        .collect();
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, external_links(events, Some("_blank")).into_iter());
+    let html = comment.clean(&html);
+    assert!(html.contains(r#"<a href="https://example.com" target="_blank" rel="nofollow ugc">a</a>"#));
+    assert!(html.contains(r#"<a href="/post/1" rel="nofollow ugc">b</a>"#));
+    assert!(!html.contains("script"));
+
+    let post = SanitizePolicy {
+        attributes: HashMap::from([("*".to_owned(), vec!["id".to_owned()])]),
+        keep_comments: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        post.clean(r#"<h2 id="a" onclick="x()">A</h2><!--post:1-->"#),
+        r#"<h2 id="a">A</h2><!--post:1-->"#
+    );
+}
@@ -0,0 +1,314 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Context;
+use once_cell::sync::Lazy;
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/// 和公式一样先替换成占位符, 单独成段, 渲染后再换回html
+const OPEN: char = '\u{E002}';
+const CLOSE: char = '\u{E003}';
+
+pub type Args = BTreeMap<String, String>;
+
+pub trait Shortcode: Send + Sync {
+    /// `body`是已经渲染好的html, 没有结束标签时为`None`
+    fn render(&self, args: &Args, body: Option<&str>) -> anyhow::Result<String>;
+}
+
+impl<F> Shortcode for F
+where
+    F: Fn(&Args, Option<&str>) -> anyhow::Result<String> + Send + Sync,
+{
+    fn render(&self, args: &Args, body: Option<&str>) -> anyhow::Result<String> {
+        self(args, body)
+    }
+}
+
+static REGISTRY: Lazy<RwLock<HashMap<String, Arc<dyn Shortcode>>>> =
+    Lazy::new(|| {
+        let mut registry: HashMap<String, Arc<dyn Shortcode>> = HashMap::new();
+        registry.insert("figure".to_owned(), Arc::new(figure));
+        registry.insert("callout".to_owned(), Arc::new(callout));
+        registry.insert("details".to_owned(), Arc::new(details));
+        RwLock::new(registry)
+    });
+
+/// 同名的会覆盖之前注册的, 包括内置的
+pub fn register<S>(name: impl Into<String>, shortcode: S)
+where
+    S: Shortcode + 'static,
+{
+    REGISTRY
+        .write()
+        .unwrap()
+        .insert(name.into(), Arc::new(shortcode));
+}
+
+fn get(name: &str) -> Option<Arc<dyn Shortcode>> {
+    REGISTRY.read().unwrap().get(name).cloned()
+}
+
+#[derive(Debug, PartialEq)]
+struct ShortcodeTag<'a> {
+    name: &'a str,
+    args: Args,
+    closing: bool,
+}
+
+/// 独占一行的`{{< name key="value" >}}`或`{{< /name >}}`
+fn parse_tag(line: &str) -> Option<ShortcodeTag<'_>> {
+    let inner = line
+        .trim()
+        .strip_prefix("{{<")?
+        .strip_suffix(">}}")?
+        .trim();
+    if let Some(name) = inner.strip_prefix('/') {
+        return Some(ShortcodeTag {
+            name: name.trim(),
+            args: Args::new(),
+            closing: true,
+        });
+    }
+
+    let name_end = inner.find(char::is_whitespace).unwrap_or(inner.len());
+    let name = &inner[..name_end];
+    if name.is_empty() {
+        return None;
+    }
+    Some(ShortcodeTag {
+        name,
+        args: parse_args(&inner[name_end..])?,
+        closing: false,
+    })
+}
+
+/// `key="value"`, `key=value`, 只有`key`时值为`true`
+fn parse_args(s: &str) -> Option<Args> {
+    let mut args = Args::new();
+    let mut chars = s.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut key = String::new();
+        while let Some(c) = chars.next_if(|c| !c.is_whitespace() && *c != '=') {
+            key.push(c);
+        }
+        if key.is_empty() {
+            return chars.peek().is_none().then(|| args);
+        }
+        if chars.next_if_eq(&'=').is_none() {
+            args.insert(key, "true".to_owned());
+            continue;
+        }
+
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => value.push(chars.next()?),
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        args.insert(key, value);
+    }
+}
+
+/// 找到已注册的shortcode并渲染, 原文中替换成占位符.
+/// 跳过fenced code block, 未注册的名字原样保留
+pub fn extract<F>(src: &str, render_body: F) -> anyhow::Result<(String, Vec<String>)>
+where
+    F: Fn(&str) -> anyhow::Result<String>,
+{
+    let lines = src.split_inclusive('\n').collect::<Vec<_>>();
+    let mut out = String::with_capacity(src.len());
+    let mut blocks = Vec::new();
+    let mut fence: Option<(char, usize)> = None;
+    let mut idx = 0;
+
+    while idx < lines.len() {
+        let line = lines[idx];
+        idx += 1;
+        if let Some(open) = fence {
+            out.push_str(line);
+            if super::closes_fence(line, open) {
+                fence = None;
+            }
+            continue;
+        }
+        if let Some(open) = super::fence(line) {
+            out.push_str(line);
+            fence = Some(open);
+            continue;
+        }
+
+        let (tag, shortcode) = match parse_tag(line)
+            .filter(|tag| !tag.closing)
+            .and_then(|tag| get(tag.name).map(|shortcode| (tag, shortcode)))
+        {
+            Some(found) => found,
+            None => {
+                out.push_str(line);
+                continue;
+            }
+        };
+
+        let body = match closing_line(&lines[idx..], tag.name) {
+            Some(end) => {
+                let body = render_body(&lines[idx..idx + end].concat())?;
+                idx += end + 1;
+                Some(body)
+            }
+            None => None,
+        };
+        let html = shortcode
+            .render(&tag.args, body.as_deref())
+            .with_context(|| format!("render shortcode `{}`", tag.name))?;
+
+        out.push('\n');
+        out.push(OPEN);
+        out.push_str(&blocks.len().to_string());
+        out.push(CLOSE);
+        out.push_str("\n\n");
+        blocks.push(html);
+    }
+
+    Ok((out, blocks))
+}
+
+/// 同名的shortcode可以嵌套
+fn closing_line(lines: &[&str], name: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (idx, line) in lines.iter().enumerate() {
+        match parse_tag(line) {
+            Some(tag) if tag.name == name && !tag.closing => depth += 1,
+            Some(tag) if tag.name == name && depth == 0 => return Some(idx),
+            Some(tag) if tag.name == name => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// 只剩占位符的段落换成渲染好的html
+pub fn expand<'a>(events: Vec<Event<'a>>, blocks: &[String]) -> Vec<Event<'a>> {
+    if blocks.is_empty() {
+        return events;
+    }
+    let mut out = Vec::with_capacity(events.len());
+    for event in events {
+        if let Event::End(Tag::Paragraph) = event {
+            if let [.., Event::Start(Tag::Paragraph), Event::Text(text)] = out.as_slice() {
+                if let Some(html) = placeholder(text).and_then(|idx| blocks.get(idx)) {
+                    out.truncate(out.len() - 2);
+                    out.push(Event::Html(CowStr::from(html.clone())));
+                    continue;
+                }
+            }
+        }
+        out.push(event);
+    }
+    out
+}
+
+fn placeholder(text: &str) -> Option<usize> {
+    text.trim()
+        .strip_prefix(OPEN)?
+        .strip_suffix(CLOSE)?
+        .parse()
+        .ok()
+}
+
+/// 去掉shortcode的标签行, 只保留内容, 用于生成纯文本
+pub fn strip(src: &str) -> String {
+    src.split_inclusive('\n')
+        .filter(|line| parse_tag(line).map_or(true, |tag| get(tag.name).is_none()))
+        .collect()
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    escape_html(&mut out, s).ok();
+    out
+}
+
+fn arg<'a>(args: &'a Args, key: &str) -> Option<&'a str> {
+    args.get(key).map(String::as_str)
+}
+
+/// `{{< figure src="a.png" alt="..." caption="..." >}}`, 有内容时内容作为标题
+fn figure(args: &Args, body: Option<&str>) -> anyhow::Result<String> {
+    let src = arg(args, "src").context("`figure` requires `src`")?;
+    let mut href = String::new();
+    escape_href(&mut href, src).ok();
+    let mut html = format!(
+        r#"<figure><img src="{}" alt="{}">"#,
+        href,
+        escape(arg(args, "alt").unwrap_or_default())
+    );
+    let caption = body
+        .map(str::to_owned)
+        .or_else(|| arg(args, "caption").map(escape));
+    if let Some(caption) = caption {
+        html.push_str("<figcaption>");
+        html.push_str(&caption);
+        html.push_str("</figcaption>");
+    }
+    html.push_str("</figure>\n");
+    Ok(html)
+}
+
+/// `{{< callout type="warning" title="..." >}}`, type默认为note
+fn callout(args: &Args, body: Option<&str>) -> anyhow::Result<String> {
+    let kind = arg(args, "type").unwrap_or("note");
+    anyhow::ensure!(
+        kind.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'),
+        "invalid callout type `{}`",
+        kind
+    );
+    let mut html = format!(r#"<div class="callout callout-{}">"#, kind);
+    if let Some(title) = arg(args, "title") {
+        html.push_str(&format!(r#"<p class="callout-title">{}</p>"#, escape(title)));
+    }
+    html.push('\n');
+    html.push_str(body.unwrap_or_default());
+    html.push_str("</div>\n");
+    Ok(html)
+}
+
+/// `{{< details summary="..." open >}}`
+fn details(args: &Args, body: Option<&str>) -> anyhow::Result<String> {
+    Ok(format!(
+        "<details{}><summary>{}</summary>\n{}</details>\n",
+        if args.contains_key("open") { " open" } else { "" },
+        escape(arg(args, "summary").unwrap_or("Details")),
+        body.unwrap_or_default()
+    ))
+}
+
+#[test]
+fn parse_tag_test() {
+    let tag = parse_tag(r#"{{< figure src="a b.png" alt=x open >}}"#).unwrap();
+    assert_eq!(tag.name, "figure");
+    assert_eq!(tag.args["src"], "a b.png");
+    assert_eq!(tag.args["alt"], "x");
+    assert_eq!(tag.args["open"], "true");
+    assert!(parse_tag("{{< /figure >}}").unwrap().closing);
+    assert!(parse_tag(r#"{{< figure src="a >}}"#).is_none());
+    assert!(parse_tag("{{ figure }}").is_none());
+}
+
+#[test]
+fn extract_test() {
+    let src = "a\n{{< details summary=\"more\" >}}\n*b*\n{{< /details >}}\n```\n{{< details >}}\n```\n{{< unknown >}}\n";
+    let (out, blocks) = extract(src, |body| Ok(format!("[{}]", body.trim()))).unwrap();
+    assert_eq!(blocks, ["<details><summary>more</summary>\n[*b*]</details>\n"]);
+    assert!(out.contains("```\n{{< details >}}\n```"));
+    assert!(out.ends_with("{{< unknown >}}\n"));
+}
@@ -0,0 +1,162 @@
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+
+/// 手动指定摘要的分隔符, 之前的内容作为摘要
+pub const MORE_MARKER: &str = "<!--more-->";
+
+/// 每分钟阅读的中日韩字符数和其他语言的单词数
+const CJK_PER_MINUTE: usize = 400;
+const WORDS_PER_MINUTE: usize = 200;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Summary {
+    pub excerpt_html: String,
+    pub word_count: u32,
+    pub reading_minutes: u32,
+}
+
+/// 有`<!--more-->`时渲染它之前的部分, 否则从纯文本中截取`excerpt_length`个字符
+pub fn summary(s: &str, excerpt_length: usize) -> anyhow::Result<Summary> {
+    let text = plain_text(s);
+    let (cjk, words) = count_words(&text);
+    let minutes = (cjk as f64 / CJK_PER_MINUTE as f64
+        + words as f64 / WORDS_PER_MINUTE as f64)
+        .ceil() as u32;
+
+    let excerpt_html = match more_marker(s) {
+        Some(idx) => super::render(&s[..idx])?,
+        None => excerpt(&text, excerpt_length),
+    };
+
+    Ok(Summary {
+        excerpt_html,
+        word_count: (cjk + words) as u32,
+        reading_minutes: minutes,
+    })
+}
+
+/// 只认作为html出现的分隔符, 代码中的不算
+fn more_marker(s: &str) -> Option<usize> {
+    Parser::new_ext(s, Options::all())
+        .into_offset_iter()
+        .find_map(|(event, range)| match event {
+            Event::Html(html) if html.trim_start().starts_with(MORE_MARKER) => {
+                s[range.clone()].find(MORE_MARKER).map(|idx| range.start + idx)
+            }
+            _ => None,
+        })
+}
+
+/// 去掉markdown语法, 代码块和shortcode标签后的文本
+fn plain_text(s: &str) -> String {
+    let s = super::shortcode::strip(s);
+    let mut text = String::with_capacity(s.len());
+    let mut in_code_block = false;
+    for event in Parser::new_ext(&s, Options::all()) {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => in_code_block = true,
+            Event::End(Tag::CodeBlock(_)) => in_code_block = false,
+            Event::Text(s) | Event::Code(s) if !in_code_block => {
+                text.push_str(&s)
+            }
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(Tag::Paragraph | Tag::Heading(_) | Tag::Item) => {
+                text.push(' ')
+            }
+            _ => {}
+        }
+    }
+    text
+}
+
+#[inline]
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' // 平假名, 片假名
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{AC00}'..='\u{D7AF}' // 谚文
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{20000}'..='\u{2FA1F}')
+}
+
+/// 中日韩字符每个算一个词, 其他语言按连续的字母数字算一个词
+fn count_words(text: &str) -> (usize, usize) {
+    let mut cjk = 0;
+    let mut words = 0;
+    let mut in_word = false;
+    for c in text.chars() {
+        if is_cjk(c) {
+            cjk += 1;
+            in_word = false;
+        } else if c.is_alphanumeric() {
+            if !in_word {
+                words += 1;
+            }
+            in_word = true;
+        } else if c != '\'' && c != '-' {
+            in_word = false;
+        }
+    }
+    (cjk, words)
+}
+
+/// 不截断单词, 中日韩字符之间可以直接截断
+fn excerpt(text: &str, length: usize) -> String {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let cut = match text.char_indices().nth(length) {
+        None => None,
+        Some((idx, c)) => {
+            let before = &text[..idx];
+            if is_cjk(c) || c.is_whitespace() {
+                Some(before)
+            } else {
+                Some(match before.rfind(|c: char| c.is_whitespace() || is_cjk(c)) {
+                    Some(space) if space > 0 => {
+                        let c = before[space..].chars().next().unwrap();
+                        if c.is_whitespace() {
+                            &before[..space]
+                        } else {
+                            &before[..space + c.len_utf8()]
+                        }
+                    }
+                    _ => before,
+                })
+            }
+        }
+    };
+
+    let mut html = String::from("<p>");
+    escape_html(&mut html, cut.unwrap_or(&text).trim_end()).ok();
+    if cut.is_some() {
+        html.push('…');
+    }
+    html.push_str("</p>\n");
+    html
+}
+
+#[test]
+fn count_words_test() {
+    assert_eq!(count_words("hello world, it's a test"), (0, 5));
+    assert_eq!(count_words("你好世界 hello"), (4, 1));
+}
+
+#[test]
+fn excerpt_test() {
+    assert_eq!(excerpt("hello world", 20), "<p>hello world</p>\n");
+    assert_eq!(excerpt("hello wonderful world", 8), "<p>hello…</p>\n");
+    assert_eq!(excerpt("你好世界", 2), "<p>你好…</p>\n");
+    assert_eq!(excerpt("a < b", 20), "<p>a &lt; b</p>\n");
+}
+
+#[test]
+fn summary_test() {
+    let summary = summary("# Title\n\nintro\n\n<!--more-->\n\nrest", 100).unwrap();
+    assert!(summary.excerpt_html.contains("intro"));
+    assert!(!summary.excerpt_html.contains("rest"));
+    assert_eq!(summary.word_count, 3);
+    assert_eq!(summary.reading_minutes, 1);
+
+    let code = "`<!--more-->`\n\n```\n<!--more-->\n```\n\nintro <!--more--> rest";
+    assert_eq!(more_marker(code), code.rfind(MORE_MARKER));
+}
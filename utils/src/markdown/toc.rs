@@ -0,0 +1,126 @@
+use std::collections::HashSet;
+
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{CowStr, Event, Tag};
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct TocEntry {
+    pub level: u32,
+    pub id: String,
+    /// 已经转义过的标题文本
+    pub title: String,
+    pub children: Vec<TocEntry>,
+}
+
+/// 保留所有语言的字母和数字, 这样中文标题也能得到可读的id
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::with_capacity(text.len());
+    for c in text.trim().chars() {
+        if c.is_alphanumeric() || c == '_' {
+            slug.extend(c.to_lowercase());
+        } else if (c.is_whitespace() || c == '-') && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_matches('-');
+    if slug.is_empty() {
+        "section".to_owned()
+    } else {
+        slug.to_owned()
+    }
+}
+
+/// 给标题加上id和可选的锚点链接, 同时收集目录.
+/// 重复的id依次加上`-1`, `-2`...
+pub fn headings<'a>(
+    events: Vec<Event<'a>>,
+    anchors: bool,
+) -> (Vec<Event<'a>>, Vec<TocEntry>) {
+    let mut out = Vec::with_capacity(events.len());
+    let mut flat = Vec::new();
+    let mut used = HashSet::new();
+    let mut heading: Option<(u32, Vec<Event<'a>>, String)> = None;
+
+    for event in events {
+        match event {
+            Event::Start(Tag::Heading(level)) => {
+                heading = Some((level, Vec::new(), String::new()))
+            }
+            Event::End(Tag::Heading(_)) if heading.is_some() => {
+                let (level, inner, text) = heading.take().unwrap();
+                let slug = slugify(&text);
+                let mut id = slug.clone();
+                let mut n = 0;
+                while used.contains(&id) {
+                    n += 1;
+                    id = format!("{}-{}", slug, n);
+                }
+                used.insert(id.clone());
+
+                let mut open = format!(r#"<h{} id="{}">"#, level, id);
+                if anchors {
+                    open.push_str(&format!(
+                        r##"<a class="heading-anchor" href="#{}" aria-hidden="true">#</a>"##,
+                        id
+                    ));
+                }
+                out.push(Event::Html(CowStr::from(open)));
+                out.extend(inner);
+                out.push(Event::Html(CowStr::from(format!("</h{}>\n", level))));
+
+                let mut title = String::with_capacity(text.len());
+                escape_html(&mut title, text.trim()).ok();
+                flat.push(TocEntry {
+                    level,
+                    id,
+                    title,
+                    children: Vec::new(),
+                });
+            }
+            event => match &mut heading {
+                Some((_, inner, text)) => {
+                    if let Event::Text(s) | Event::Code(s) = &event {
+                        text.push_str(s);
+                    }
+                    inner.push(event);
+                }
+                None => out.push(event),
+            },
+        }
+    }
+
+    let mut toc = Vec::new();
+    flat.into_iter().for_each(|entry| nest(&mut toc, entry));
+    (out, toc)
+}
+
+/// 级别更低的标题作为前一个标题的子项
+fn nest(list: &mut Vec<TocEntry>, entry: TocEntry) {
+    match list.last_mut() {
+        Some(last) if last.level < entry.level => {
+            nest(&mut last.children, entry)
+        }
+        _ => list.push(entry),
+    }
+}
+
+#[test]
+fn slugify_test() {
+    assert_eq!(slugify("Hello, World!"), "hello-world");
+    assert_eq!(slugify("  Rust 与 异步  "), "rust-与-异步");
+    assert_eq!(slugify("C++ / C#"), "c-c");
+    assert_eq!(slugify("!!!"), "section");
+}
+
+#[test]
+fn headings_test() {
+    use pulldown_cmark::Parser;
+
+    let events = Parser::new("# A\n## B\n## B\n# 中文\n").collect();
+    let (_, toc) = headings(events, false);
+    assert_eq!(toc.len(), 2);
+    assert_eq!(toc[0].id, "a");
+    assert_eq!(toc[0].children[0].id, "b");
+    assert_eq!(toc[0].children[1].id, "b-1");
+    assert_eq!(toc[1].id, "中文");
+}
@@ -1,18 +1,31 @@
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
 use crossfire::mpsc::{unbounded_future, RxUnbounded, TxUnbounded};
 use tokio::sync::Mutex;
 
 pub struct Notify {
     targets: Mutex<Vec<Target>>,
+    notified: AtomicBool,
+    next_id: AtomicUsize,
 }
 
 impl Notify {
     pub fn new() -> Self {
         Notify {
             targets: Mutex::new(Vec::new()),
+            notified: AtomicBool::new(false),
+            next_id: AtomicUsize::new(0),
         }
     }
 
+    /// `notify`开始之后就一直返回true
+    #[inline]
+    pub fn is_notified(&self) -> bool {
+        self.notified.load(Ordering::Acquire)
+    }
+
     pub async fn notify(&self) {
+        self.notified.store(true, Ordering::Release);
         let mut targets = self.targets.lock().await;
         targets.sort_by(|t1, t2| {
             t1.priority.cmp(&t2.priority).reverse()
@@ -25,14 +38,24 @@ impl Notify {
     }
 
     pub async fn register(&self, priority: usize) -> WaitHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
         let (tx, rx) = unbounded_future();
         let (tx2, rx2) = unbounded_future();
         self.targets.lock().await.push(Target {
+            id,
             priority,
             tx,
             rx: rx2,
         });
-        WaitHandle { tx: tx2, rx }
+        WaitHandle { id, tx: tx2, rx }
+    }
+
+    /// 不再需要等待通知时移除, 比如提前停止的监听器.
+    /// 先drop掉handle, 正在进行的`notify`就不会等待它
+    pub async fn deregister(&self, handle: WaitHandle) {
+        let id = handle.id;
+        drop(handle);
+        self.targets.lock().await.retain(|target| target.id != id);
     }
 }
 
@@ -43,12 +66,14 @@ impl Default for Notify {
 }
 
 struct Target {
+    id: usize,
     priority: usize,
     tx: TxUnbounded<()>,
     rx: RxUnbounded<()>,
 }
 
 pub struct WaitHandle {
+    id: usize,
     tx: TxUnbounded<()>,
     rx: RxUnbounded<()>,
 }
@@ -0,0 +1,186 @@
+//! systemd的`sd_notify`协议, 没有设置`NOTIFY_SOCKET`时什么都不做
+//!
+//! 注意这里不能打印log, 因为config刷新时也会调用
+
+use std::io;
+#[cfg(target_family = "unix")]
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::time::Duration;
+
+const NOTIFY_SOCKET: &str = "NOTIFY_SOCKET";
+
+/// 向`NOTIFY_SOCKET`发送状态, 返回是否真的发送了
+pub fn notify(state: &str) -> io::Result<bool> {
+    match std::env::var_os(NOTIFY_SOCKET) {
+        Some(path) => notify_to(Path::new(&path), state).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+#[cfg(not(target_family = "unix"))]
+fn notify_to(_path: &Path, _state: &str) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_family = "unix")]
+fn notify_to(path: &Path, state: &str) -> io::Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let socket = UnixDatagram::unbound()?;
+    match path.as_os_str().as_bytes().strip_prefix(b"@") {
+        Some(name) => send_to_abstract(&socket, name, state),
+        None => socket.send_to(state.as_bytes(), path).map(|_| ()),
+    }
+}
+
+/// `@`开头的是linux的抽象socket, 地址中用NUL代替`@`
+#[cfg(target_os = "linux")]
+fn abstract_addr(
+    name: &[u8],
+) -> io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    let mut addr: libc::sockaddr_un = unsafe { std::mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+    if name.len() + 1 > addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "notify socket name is too long",
+        ));
+    }
+    addr.sun_path[1..=name.len()]
+        .iter_mut()
+        .zip(name)
+        .for_each(|(dst, src)| *dst = *src as libc::c_char);
+    let len = std::mem::size_of::<libc::sa_family_t>() + 1 + name.len();
+    Ok((addr, len as libc::socklen_t))
+}
+
+#[cfg(target_os = "linux")]
+fn send_to_abstract(
+    socket: &UnixDatagram,
+    name: &[u8],
+    state: &str,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let (addr, len) = abstract_addr(name)?;
+    let sent = unsafe {
+        libc::sendto(
+            socket.as_raw_fd(),
+            state.as_ptr() as *const libc::c_void,
+            state.len(),
+            libc::MSG_NOSIGNAL,
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(target_family = "unix", not(target_os = "linux")))]
+fn send_to_abstract(
+    _socket: &UnixDatagram,
+    _name: &[u8],
+    _state: &str,
+) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "abstract notify socket is only supported on linux",
+    ))
+}
+
+#[inline]
+pub fn ready() -> io::Result<bool> {
+    notify("READY=1")
+}
+
+#[inline]
+pub fn stopping() -> io::Result<bool> {
+    notify("STOPPING=1")
+}
+
+/// `Type=notify-reload`要求同时带上`MONOTONIC_USEC`
+pub fn reloading() -> io::Result<bool> {
+    notify(&format!("RELOADING=1\nMONOTONIC_USEC={}", monotonic_usec()))
+}
+
+#[inline]
+pub fn watchdog() -> io::Result<bool> {
+    notify("WATCHDOG=1")
+}
+
+/// systemd开启了watchdog时返回`WatchdogSec`
+pub fn watchdog_timeout() -> Option<Duration> {
+    let pid_matched = std::env::var("WATCHDOG_PID")
+        .map(|pid| pid.parse::<u32>().ok() == Some(std::process::id()))
+        .unwrap_or(true);
+    std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|usec| usec.parse::<u64>().ok())
+        .filter(|usec| pid_matched && *usec > 0)
+        .map(Duration::from_micros)
+}
+
+#[cfg(not(target_family = "unix"))]
+fn monotonic_usec() -> u64 {
+    0
+}
+
+#[cfg(target_family = "unix")]
+fn monotonic_usec() -> u64 {
+    let mut ts = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut ts) };
+    ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000
+}
+
+#[cfg(target_family = "unix")]
+#[test]
+fn test_notify() {
+    let path = std::env::temp_dir()
+        .join(format!("maop-notify-{}.sock", std::process::id()));
+    std::fs::remove_file(&path).ok();
+    let socket = UnixDatagram::bind(&path).unwrap();
+
+    notify_to(&path, "READY=1").unwrap();
+    let mut buf = [0; 64];
+    let len = socket.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"READY=1");
+
+    let usec = monotonic_usec();
+    notify_to(&path, &format!("RELOADING=1\nMONOTONIC_USEC={}", usec))
+        .unwrap();
+    let len = socket.recv(&mut buf).unwrap();
+    let msg = std::str::from_utf8(&buf[..len]).unwrap();
+    assert!(msg.starts_with("RELOADING=1\nMONOTONIC_USEC="));
+
+    std::fs::remove_file(&path).ok();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn test_notify_abstract() {
+    use std::os::unix::io::AsRawFd;
+
+    let name = format!("maop-notify-{}", std::process::id());
+    let socket = UnixDatagram::unbound().unwrap();
+    let (addr, len) = abstract_addr(name.as_bytes()).unwrap();
+    let ret = unsafe {
+        libc::bind(
+            socket.as_raw_fd(),
+            &addr as *const libc::sockaddr_un as *const libc::sockaddr,
+            len,
+        )
+    };
+    assert_eq!(ret, 0);
+
+    notify_to(Path::new(&format!("@{}", name)), "READY=1").unwrap();
+    let mut buf = [0; 64];
+    let len = socket.recv(&mut buf).unwrap();
+    assert_eq!(&buf[..len], b"READY=1");
+}